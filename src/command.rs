@@ -1,3 +1,17 @@
+pub mod activity;
+pub mod break_glass;
+pub mod doctor;
+pub mod elevate;
+pub mod explain;
+pub mod gc;
 pub mod generate;
+pub mod list;
+pub mod profile;
+pub mod refresh_ca;
+pub mod report;
+pub mod revoke;
+pub mod schedule;
 pub mod setup;
 pub mod structure;
+pub mod teardown;
+pub mod unrevoke;
@@ -0,0 +1,56 @@
+use crate::{
+    command::structure::ActivityArgs,
+    core::{
+        activity,
+        audit::{self, AuditFilter},
+        client::ClientManager,
+        ledger,
+    },
+    error::*,
+    output,
+};
+
+/// Matches the audit log against the ledger to see what issued identities did
+pub async fn handle(arguments: ActivityArgs) -> Result<()> {
+    if arguments.json {
+        tracing::warn!("--json is deprecated, use --output json");
+    }
+
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let grants = ledger::list_with_csrs(&client, arguments.user.as_deref()).await?;
+
+    let filter = AuditFilter {
+        user: None,
+        group: None,
+        since: arguments.since,
+        until: arguments.until,
+    };
+    let mut events = audit::read_events(&arguments.audit_log, &filter).await?;
+
+    // The certificate authenticates the request, impersonating someone else still counts
+    events.retain(|event| {
+        arguments
+            .user
+            .as_ref()
+            .is_none_or(|user| &event.user.username == user)
+    });
+
+    let activities = activity::summarize(&events, &grants);
+    let json = arguments.json || output::json();
+    let report = if json {
+        activity::render_json(&activities)
+    } else {
+        activity::render_text(&activities)
+    };
+
+    match &arguments.report {
+        Some(path) => tokio::fs::write(path, report).await?,
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use crate::{
+    command::structure::{BindingMode, BreakGlassArgs},
+    core::{
+        ca,
+        client::ClientManager,
+        csr::SignerOptions,
+        event, hook,
+        issue::{self, IssueRequest},
+        profile,
+    },
+    error::*,
+    output::{self, say},
+    shared,
+};
+
+use k8s_openapi::api::core::v1::ObjectReference;
+use tokio::fs;
+
+/// Shortest validity the CSR api accepts
+const MIN_DURATION_SECONDS: i64 = 600;
+
+/// Emergency admin access: short lived, justified, announced with an Event
+/// before anything is issued and with the notification hook afterwards
+pub async fn handle(arguments: BreakGlassArgs) -> Result<()> {
+    if arguments.reason.trim().is_empty() {
+        return Err(CoralGateError::MissingReason);
+    }
+
+    let mut duration = arguments.duration;
+    if duration > arguments.max_duration {
+        say!(
+            "{}m is above the break-glass limit, capped to {}m",
+            duration.num_minutes(),
+            arguments.max_duration.num_minutes()
+        );
+        duration = arguments.max_duration;
+    }
+    if duration.num_seconds() < MIN_DURATION_SECONDS {
+        return Err(CoralGateError::InvalidDuration(format!(
+            "break-glass credentials must be valid for at least {}m",
+            MIN_DURATION_SECONDS / 60
+        )));
+    }
+
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+    ca::resolve(
+        &mut client_manager,
+        &client,
+        arguments.kubeconfig.as_deref(),
+        arguments.ca_file.as_deref(),
+    )
+    .await?;
+
+    event::warn(
+        &client,
+        "BreakGlass",
+        &format!(
+            "{} requested break-glass admin access for {}m: {}",
+            arguments.user,
+            duration.num_minutes(),
+            arguments.reason
+        ),
+        None,
+    )
+    .await?;
+
+    let request = IssueRequest {
+        user: arguments.user.clone(),
+        profile: profile::admin_profile(),
+        namespace: None,
+        binding_mode: BindingMode::Group,
+        expiration_seconds: shared::expiration_seconds(duration)?,
+        reason: Some(arguments.reason.clone()),
+        break_glass: true,
+        skip_verify: arguments.skip_verify,
+        signer: SignerOptions::default(),
+        csr_retention: chrono::Duration::zero(),
+    };
+
+    let issued = issue::issue(&client, &client_manager, &request).await?;
+
+    event::warn(
+        &client,
+        "BreakGlassIssued",
+        &format!(
+            "{} holds break-glass admin access until {} (serial {}): {}",
+            issued.grant.user,
+            issued.grant.expires_at.to_rfc3339(),
+            issued.grant.serial,
+            arguments.reason
+        ),
+        Some(ObjectReference {
+            api_version: Some("certificates.k8s.io/v1".into()),
+            kind: Some("CertificateSigningRequest".into()),
+            name: Some(issued.grant.csr.clone()),
+            ..Default::default()
+        }),
+    )
+    .await?;
+
+    fs::write("kubeconfig", issued.kubeconfig.as_bytes()).await?;
+    tracing::info!(path = "kubeconfig", "wrote kubeconfig");
+
+    // The credential is out already, a broken hook must not hide it
+    if let Some(command) = &arguments.notify
+        && let Err(error) = hook::notify(command, "break-glass", &issued.grant).await
+    {
+        tracing::warn!(%error, "notification hook failed");
+    }
+
+    say!(
+        "break-glass kubeconfig for {} written, valid until {}",
+        issued.grant.user,
+        issued.grant.expires_at.to_rfc3339()
+    );
+
+    output::result(&serde_json::json!({
+        "kubeconfig": "kubeconfig",
+        "grant": issued.grant,
+    }));
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use crate::{
+    command::structure::DoctorArgs,
+    core::{
+        client::ClientManager,
+        csr::SignerOptions,
+        doctor,
+        profile::{ProfileDefinition, admin_profile, cluster_readonly_profile},
+    },
+    error::*,
+    output::{self, say},
+};
+
+/// Prints a pass/fail checklist of what issuing needs, failing when anything is missing
+pub async fn handle(arguments: DoctorArgs) -> Result<()> {
+    SignerOptions {
+        name: arguments.signer_name.clone(),
+        ..Default::default()
+    }
+    .validate()?;
+
+    let mut profiles = vec![admin_profile(), cluster_readonly_profile()];
+    for path in &arguments.profile_file {
+        profiles.push(ProfileDefinition::read(path).await?.into_profile());
+    }
+
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let checks = doctor::run(&client, &arguments.signer_name, &profiles).await;
+
+    for check in &checks {
+        let state = if check.passed { "pass" } else { "FAIL" };
+        say!("[{}] {}: {}", state, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            say!("       {}", hint);
+        }
+    }
+    output::result(&checks);
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        return Err(CoralGateError::DoctorFailed(failed));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use crate::{
+    command::structure::ElevateArgs,
+    core::{
+        client::ClientManager,
+        event,
+        profile::{self, ApplyOptions, SubjectBinding},
+        revocation, rollback,
+    },
+    error::*,
+    output::{self, say},
+    shared,
+};
+
+/// Binds the profile to the subject with an expiry annotation, the binding is
+/// removed here with `--wait` or later by `gc`
+pub async fn handle(arguments: ElevateArgs) -> Result<()> {
+    let profile = profile::resolve(&arguments.profile, arguments.namespace.as_deref())?;
+
+    let mut subject = match (&arguments.user, &arguments.group) {
+        (Some(user), _) => SubjectBinding::user(user),
+        (None, Some(group)) => SubjectBinding::group(group),
+        (None, None) => return Ok(()),
+    };
+
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    if subject.subject.kind == "User"
+        && revocation::list(&client)
+            .await?
+            .iter()
+            .any(|revocation| revocation.user == subject.subject.name)
+    {
+        return Err(CoralGateError::Revoked(subject.subject.name));
+    }
+
+    let expires_at = shared::from_now(arguments.duration)?;
+    subject.suffix = format!("{}-elevated", subject.suffix);
+    subject
+        .labels
+        .insert(shared::ELEVATION_LABEL.into(), "true".into());
+    subject
+        .annotations
+        .insert(shared::EXPIRES_ANNOTATION.into(), expires_at.to_rfc3339());
+    if let Some(reason) = &arguments.reason {
+        subject
+            .annotations
+            .insert(shared::REASON_ANNOTATION.into(), reason.clone());
+    }
+
+    let bindings = profile.subject_bindings(&subject);
+    let options = ApplyOptions::default();
+
+    for binding in &bindings {
+        binding.apply(&client, &options).await?;
+        say!("bound {} until {}", binding.id(), expires_at.to_rfc3339());
+    }
+    // From here on the bindings carry their expiry, `gc` removes them
+    rollback::commit();
+
+    event::warn(
+        &client,
+        "Elevated",
+        &format!(
+            "{} {} elevated to {} until {}: {}",
+            subject.subject.kind,
+            subject.subject.name,
+            profile.name(),
+            expires_at.to_rfc3339(),
+            arguments.reason.as_deref().unwrap_or("no reason given")
+        ),
+        None,
+    )
+    .await?;
+
+    let bound: Vec<String> = bindings.iter().map(|binding| binding.id()).collect();
+    if !arguments.wait {
+        say!("run `coralgate gc` after the expiry to remove the bindings");
+        output::result(&serde_json::json!({
+            "bindings": bound,
+            "expiresAt": expires_at,
+            "removed": false,
+        }));
+        return Ok(());
+    }
+
+    tokio::time::sleep(arguments.duration.to_std().unwrap_or_default()).await;
+
+    for binding in &bindings {
+        binding.delete(&client, &options).await?;
+        say!("removed {}", binding.id());
+    }
+
+    output::result(&serde_json::json!({
+        "bindings": bound,
+        "expiresAt": expires_at,
+        "removed": true,
+    }));
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::{
+    command::structure::ExplainArgs,
+    core::{
+        certificate,
+        client::ClientManager,
+        ledger,
+        rbac::{self, Snapshot},
+    },
+    error::*,
+    output, shared,
+};
+
+use kube::config::Kubeconfig;
+
+/// Answers "what can this user do?" from the bindings that match the user or its groups
+pub async fn handle(arguments: ExplainArgs) -> Result<()> {
+    let mut user = arguments.user.clone();
+    let mut groups = arguments.group.clone();
+
+    if let Some(path) = &arguments.inspect {
+        let kubeconfig = Kubeconfig::read_from(shared::resolve_path(path)?)?;
+        let identity = certificate::identity_from_kubeconfig(&kubeconfig).await?;
+
+        user.get_or_insert(identity.user);
+        groups.extend(identity.groups);
+    }
+
+    let user = user.unwrap_or_default();
+
+    let snapshot = match &arguments.snapshot {
+        Some(path) => Snapshot::read(path).await?,
+        None => {
+            let mut client_manager = ClientManager::default();
+            let client = client_manager
+                .generate_kube_client(&arguments.kubeconfig)
+                .await?;
+
+            if groups.is_empty() && arguments.inspect.is_none() {
+                for grant in ledger::list(&client, Some(&user)).await? {
+                    if grant.is_active() {
+                        groups.extend(grant.groups);
+                    }
+                }
+            }
+
+            let snapshot = Snapshot::fetch(&client).await?;
+            if let Some(path) = &arguments.dump_snapshot {
+                snapshot.write(path).await?;
+            }
+
+            snapshot
+        }
+    };
+
+    groups.push(rbac::AUTHENTICATED_GROUP.into());
+    groups.sort();
+    groups.dedup();
+
+    let mut rules = snapshot.effective_rules(&user, &groups);
+    rules.retain(|rule| {
+        arguments.namespace.is_none()
+            || rule.namespace.is_none()
+            || rule.namespace == arguments.namespace
+    });
+
+    if output::json() {
+        output::result(&serde_json::json!({
+            "user": user,
+            "groups": groups,
+            "rules": rules,
+        }));
+        return Ok(());
+    }
+
+    println!("{} (groups: {})", user, groups.join(", "));
+
+    let rules = rbac::by_namespace(rules);
+    if rules.is_empty() {
+        println!("  no bindings match");
+    }
+
+    for (namespace, rules) in rules {
+        match &namespace {
+            Some(namespace) => println!("namespace {}:", namespace),
+            None => println!("cluster wide:"),
+        }
+
+        for rule in rules {
+            println!(
+                "  {}  via {} -> {}",
+                rbac::describe_rule(&rule.rule),
+                rule.binding,
+                rule.role
+            );
+        }
+    }
+
+    Ok(())
+}
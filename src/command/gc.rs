@@ -0,0 +1,63 @@
+use crate::{
+    command::structure::GcArgs,
+    core::{client::ClientManager, csr, inventory, revocation},
+    error::*,
+    output::{self, say},
+};
+
+/// Cleans up what outlived its expiry: elevation bindings, CSRs past their
+/// retention or never signed, and revocations of certificates that expired anyway
+pub async fn handle(arguments: GcArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let expired: Vec<inventory::ManagedObject> = inventory::elevations(&client)
+        .await?
+        .into_iter()
+        .filter(|binding| binding.is_expired())
+        .collect();
+
+    let mut removed = Vec::new();
+    for binding in &expired {
+        removed.push(format!("{} (expired)", binding));
+        if arguments.dry_run {
+            say!("would remove {} (expired)", binding);
+        } else {
+            inventory::delete(&client, binding).await?;
+            say!("removed {} (expired)", binding);
+        }
+    }
+
+    let csr_api = kube::Api::all(client.clone());
+    for (name, reason) in csr::cleanup_candidates(&csr_api, None).await? {
+        removed.push(format!("CertificateSigningRequest/{} ({})", name, reason));
+        if arguments.dry_run {
+            say!(
+                "would remove CertificateSigningRequest/{} ({})",
+                name,
+                reason
+            );
+        } else {
+            csr::delete(&csr_api, &name).await?;
+            say!("removed CertificateSigningRequest/{} ({})", name, reason);
+        }
+    }
+
+    let mut pruned = Vec::new();
+    if !arguments.dry_run {
+        pruned = revocation::prune(&client).await?;
+        for user in &pruned {
+            say!("pruned revocation of {}, its certificates expired", user);
+        }
+    }
+
+    output::result(&serde_json::json!({
+        "dryRun": arguments.dry_run,
+        "removed": removed,
+        "prunedRevocations": pruned,
+    }));
+
+    Ok(())
+}
@@ -1,71 +1,319 @@
 use crate::Result;
 use crate::command::structure::GenerateArgs;
 use crate::core::client::ClientManager;
-use crate::core::csr;
+use crate::core::csr::SignerOptions;
+use crate::core::issue::{self, IssueRequest};
+use crate::core::local_ca::LocalCa;
+use crate::core::profile::{self, Profile};
+use crate::core::roster::{self, RosterEntry};
+use crate::core::{ca, encrypt, ledger, rollback};
 use crate::error::CoralGateError;
+use crate::output::{self, say};
+use crate::shared;
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
 
-use base64::Engine;
-use base64::engine::general_purpose;
-use k8s_openapi::api::certificates::v1::CertificateSigningRequest;
 use tokio::fs;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{info, instrument};
 
 /// TODO: Create a generator, give the options to it and then call generate
 pub async fn handle(gen_arguments: GenerateArgs) -> Result<()> {
+    signer(&gen_arguments).validate()?;
+
+    if let Some(roster) = gen_arguments.roster.clone() {
+        return batch(gen_arguments, &roster).await;
+    }
+
+    let (Some(user), Some(permission)) = (&gen_arguments.user, &gen_arguments.profile) else {
+        return Ok(());
+    };
+
+    let request = IssueRequest {
+        user: user.clone(),
+        profile: profile::from_permission(permission),
+        namespace: None,
+        binding_mode: gen_arguments.binding_mode,
+        expiration_seconds: shared::expiration_seconds(chrono::Duration::hours(
+            gen_arguments.expire.into(),
+        ))?,
+        reason: gen_arguments.reason.clone(),
+        break_glass: false,
+        skip_verify: gen_arguments.skip_verify,
+        signer: signer(&gen_arguments),
+        csr_retention: csr_retention(&gen_arguments),
+    };
+    request.validate()?;
+
+    let mut client_manager = ClientManager::default();
+
+    let issued = match (&gen_arguments.ca_cert, &gen_arguments.ca_key) {
+        (Some(ca_cert), Some(ca_key)) => {
+            let local_ca = LocalCa::read(ca_cert, ca_key)?;
+            let cluster_url = match &gen_arguments.server {
+                Some(server) => server.clone(),
+                None => {
+                    client_manager
+                        .generate_kube_client(&gen_arguments.kubeconfig)
+                        .await?;
+                    client_manager.cluster_url()?
+                }
+            };
+
+            let issued = issue::issue_offline(&local_ca, &cluster_url, &request).await?;
+            say!("signed locally, the grant is not recorded in the ledger");
+            issued
+        }
+        _ => {
+            let client = client_manager
+                .generate_kube_client(&gen_arguments.kubeconfig)
+                .await?;
+            ca::resolve(
+                &mut client_manager,
+                &client,
+                gen_arguments.kubeconfig.as_deref(),
+                gen_arguments.ca_file.as_deref(),
+            )
+            .await?;
+            issue::issue(&client, &client_manager, &request).await?
+        }
+    };
+
+    write_kubeconfig(Path::new(&gen_arguments.out), issued.kubeconfig.as_bytes()).await?;
+
+    output::result(&serde_json::json!({
+        "kubeconfig": gen_arguments.out,
+        "grant": issued.grant,
+    }));
+
+    Ok(())
+}
+
+#[instrument(skip(content))]
+async fn write_kubeconfig(path: &Path, content: &[u8]) -> Result<()> {
+    fs::write(path, content)
+        .await
+        .map_err(|error| write_failed(path, error))?;
+    info!("wrote kubeconfig");
+
+    Ok(())
+}
+
+fn write_failed(path: &Path, error: std::io::Error) -> CoralGateError {
+    CoralGateError::WriteFailed {
+        path: path.display().to_string(),
+        reason: error.to_string(),
+    }
+}
+
+fn signer(arguments: &GenerateArgs) -> SignerOptions {
+    SignerOptions {
+        name: arguments.signer_name.clone(),
+        usages: arguments.usages.clone(),
+        approve: !arguments.no_approve,
+        wait: arguments.wait,
+    }
+}
+
+fn csr_retention(arguments: &GenerateArgs) -> chrono::Duration {
+    arguments
+        .csr_retention
+        .unwrap_or_else(chrono::Duration::zero)
+}
+
+/// What happened to one roster user
+#[derive(Serialize)]
+struct Outcome {
+    user: String,
+    profile: String,
+    result: &'static str,
+    detail: String,
+}
+
+/// Issues for every roster user, at most `--concurrency` at a time, and
+/// prints a summary. Users with an active grant of the profile are skipped
+/// unless `--force`
+async fn batch(arguments: GenerateArgs, roster: &str) -> Result<()> {
+    let entries = roster::read(roster, arguments.binding_mode)?;
+
     let mut client_manager = ClientManager::default();
     let client = client_manager
-        .generate_kube_client(&gen_arguments.kubeconfig)
+        .generate_kube_client(&arguments.kubeconfig)
         .await?;
+    ca::resolve(
+        &mut client_manager,
+        &client,
+        arguments.kubeconfig.as_deref(),
+        arguments.ca_file.as_deref(),
+    )
+    .await?;
+
+    let active: BTreeSet<(String, String)> = match arguments.force {
+        true => BTreeSet::new(),
+        false => ledger::list(&client, None)
+            .await?
+            .into_iter()
+            .filter(|grant| grant.is_active())
+            .map(|grant| (grant.user, grant.profile))
+            .collect(),
+    };
+
+    fs::create_dir_all(&arguments.output_dir)
+        .await
+        .map_err(|error| write_failed(Path::new(&arguments.output_dir), error))?;
+
+    // Every user's grant lands in this namespace, a failing user must not take it along
+    ledger::ensure_namespace(&client).await?;
+    rollback::commit();
+
+    let arguments = Arc::new(arguments);
+    let semaphore = Arc::new(Semaphore::new(arguments.concurrency.max(1)));
+    let mut outcomes: Vec<(usize, Outcome)> = Vec::new();
+    let mut workers = JoinSet::new();
+
+    for (index, entry) in entries.into_iter().enumerate() {
+        let profile = match profile::resolve(&entry.profile, entry.namespace.as_deref()) {
+            Ok(profile) => profile,
+            Err(error) => {
+                outcomes.push((index, failed(&entry, error)));
+                continue;
+            }
+        };
+
+        if active.contains(&(entry.user.clone(), profile.name().to_string())) {
+            outcomes.push((
+                index,
+                Outcome {
+                    user: entry.user,
+                    profile: profile.name().into(),
+                    result: "skipped",
+                    detail: "active grant, use --force to issue again".into(),
+                },
+            ));
+            continue;
+        }
+
+        let client = client.clone();
+        let client_manager = client_manager.clone();
+        let arguments = arguments.clone();
+        let semaphore = semaphore.clone();
+
+        workers.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let profile_name = profile.name().to_string();
+
+            // Each user is rolled back on its own
+            let issued = rollback::scope(issue_entry(
+                &client,
+                &client_manager,
+                &arguments,
+                &entry,
+                profile,
+            ))
+            .await;
+
+            let outcome = match issued {
+                Ok(path) => Outcome {
+                    user: entry.user,
+                    profile: profile_name,
+                    result: "issued",
+                    detail: path,
+                },
+                Err(error) => failed(&entry, error),
+            };
 
-    let self_signed_cert =
-        csr::generate_certificate(&gen_arguments.user, gen_arguments.profile.as_str()).await?;
-
-    let csr_object = csr::generate_cert_sigining_request_object(&gen_arguments, &self_signed_cert)?;
-    let csr_api: kube::Api<CertificateSigningRequest> = kube::Api::all(client);
-
-    let created_csr = csr::create(&csr_object, &csr_api).await?;
-    let approved_csr = csr::approve(&created_csr, &csr_api).await?;
-
-    let name = approved_csr
-        .metadata
-        .name
-        .ok_or_else(|| CoralGateError::UnknownTempError)?;
-
-    let signed_cert = csr::get_signed_certificate(&name, &csr_api).await?;
-
-    let signed_cert_b64 = general_purpose::STANDARD.encode(&signed_cert.0);
-    let private_key_b64 = general_purpose::STANDARD.encode(self_signed_cert.key_pem.as_bytes());
-
-    let root_ca_b64 = client_manager.root_cert_base64()?;
-    let cluster_url = client_manager.cluster_url()?;
-
-    let kubeconfig_yaml = format!(
-        r#"apiVersion: v1
-kind: Config
-clusters:
-- cluster:
-    certificate-authority-data: {root_ca}
-    server: {cluster_url}
-  name: cluster-default
-contexts:
-- context:
-    cluster: cluster-default
-    user: {user}
-  name: {user}-context
-current-context: {user}-context
-users:
-- name: {user}
-  user:
-    client-certificate-data: {cert}
-    client-key-data: {key}
-"#,
-        root_ca = root_ca_b64,
-        cluster_url = cluster_url,
-        user = gen_arguments.user,
-        cert = signed_cert_b64,
-        key = private_key_b64
-    );
-
-    fs::write("kubeconfig", kubeconfig_yaml.as_bytes()).await;
+            (index, outcome)
+        });
+    }
+
+    while let Some(finished) = workers.join_next().await {
+        match finished {
+            Ok(outcome) => outcomes.push(outcome),
+            // Workers are never aborted, so this is a panic
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+    outcomes.sort_by_key(|(index, _)| *index);
+
+    if output::json() {
+        let outcomes: Vec<&Outcome> = outcomes.iter().map(|(_, outcome)| outcome).collect();
+        output::result(&outcomes);
+    } else {
+        println!("{:<24} {:<28} {:<8} DETAIL", "USER", "PROFILE", "RESULT");
+        for (_, outcome) in &outcomes {
+            println!(
+                "{:<24} {:<28} {:<8} {}",
+                outcome.user, outcome.profile, outcome.result, outcome.detail
+            );
+        }
+    }
+
+    let failures = outcomes
+        .iter()
+        .filter(|(_, outcome)| outcome.result == "failed")
+        .count();
+    if failures > 0 {
+        return Err(CoralGateError::BatchFailed(failures));
+    }
 
     Ok(())
 }
+
+fn failed(entry: &RosterEntry, error: CoralGateError) -> Outcome {
+    Outcome {
+        user: entry.user.clone(),
+        profile: entry.profile.clone(),
+        result: "failed",
+        detail: error.to_string(),
+    }
+}
+
+/// Issues one roster user and writes the kubeconfig, encrypted when the
+/// entry names a recipient. Returns the file written
+async fn issue_entry(
+    client: &kube::Client,
+    client_manager: &ClientManager,
+    arguments: &GenerateArgs,
+    entry: &RosterEntry,
+    profile: Profile,
+) -> Result<String> {
+    let expiration_seconds = match &entry.expire {
+        Some(expire) => shared::expiration_seconds(
+            shared::parse_duration(expire).map_err(CoralGateError::InvalidDuration)?,
+        )?,
+        None => shared::expiration_seconds(chrono::Duration::hours(arguments.expire.into()))?,
+    };
+
+    let request = IssueRequest {
+        user: entry.user.clone(),
+        profile,
+        namespace: entry.namespace.clone(),
+        binding_mode: entry.binding_mode.unwrap_or(arguments.binding_mode),
+        expiration_seconds,
+        reason: entry.reason.clone().or_else(|| arguments.reason.clone()),
+        break_glass: false,
+        skip_verify: arguments.skip_verify,
+        signer: signer(arguments),
+        csr_retention: csr_retention(arguments),
+    };
+
+    let issued = issue::issue(client, client_manager, &request).await?;
+
+    let file_name = format!("{}.kubeconfig", shared::sanitize_name(&entry.user));
+    let (file_name, content) = match &entry.recipient {
+        Some(recipient) => (
+            format!("{}.age", file_name),
+            encrypt::age(recipient, issued.kubeconfig.as_bytes()).await?,
+        ),
+        None => (file_name, issued.kubeconfig.into_bytes()),
+    };
+
+    let path = Path::new(&arguments.output_dir).join(file_name);
+    write_kubeconfig(&path, &content).await?;
+
+    Ok(path.display().to_string())
+}
@@ -0,0 +1,59 @@
+use crate::{
+    command::structure::ListArgs,
+    core::{client::ClientManager, ledger},
+    error::*,
+    output,
+};
+
+/// Active grants from the ledger, completed with issued CSRs it missed
+pub async fn handle(arguments: ListArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let grants: Vec<ledger::Grant> = ledger::list_with_csrs(&client, arguments.user.as_deref())
+        .await?
+        .into_iter()
+        .filter(|grant| arguments.all || grant.is_active())
+        .collect();
+
+    if output::json() {
+        output::result(&grants);
+        return Ok(());
+    }
+
+    if grants.is_empty() {
+        println!("No grants");
+        return Ok(());
+    }
+
+    println!(
+        "{:<24} {:<18} {:<11} {:<8} {:<26} {:<26} {:<8} SERIAL",
+        "USER", "PROFILE", "KIND", "BINDING", "ISSUED", "EXPIRES", "STATE"
+    );
+
+    for grant in grants {
+        println!(
+            "{:<24} {:<18} {:<11} {:<8} {:<26} {:<26} {:<8} {}",
+            grant.user,
+            grant.profile,
+            if grant.break_glass {
+                "break-glass"
+            } else {
+                "standard"
+            },
+            grant.binding_mode,
+            grant.issued_at.to_rfc3339(),
+            grant.expires_at.to_rfc3339(),
+            if grant.is_active() {
+                "active"
+            } else {
+                "expired"
+            },
+            grant.serial
+        );
+    }
+
+    Ok(())
+}
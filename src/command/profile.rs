@@ -0,0 +1,235 @@
+use crate::{
+    command::structure::{
+        ProfileArgs, ProfileCommands, ProfileLintArgs, ProfileSynthesizeArgs, ProfileTestArgs,
+        ReportFormat,
+    },
+    core::{
+        audit::{self, AuditFilter},
+        client::ClientManager,
+        lint,
+        profile::{
+            Profile, ProfileDefinition, admin_profile, cluster_readonly_profile, namespaced_admin,
+            namespaced_edit, namespaced_readonly,
+        },
+        rbac::{self, Snapshot},
+        suite::{self, Suite},
+        synthesize::{self, Usage},
+    },
+    error::*,
+    output::{self, say},
+    shared,
+};
+
+pub async fn handle(arguments: ProfileArgs) -> Result<()> {
+    match arguments.command {
+        ProfileCommands::Test(test_arguments) => test(test_arguments).await,
+        ProfileCommands::Lint(lint_arguments) => lint(lint_arguments).await,
+        ProfileCommands::Synthesize(synthesize_arguments) => synthesize(synthesize_arguments).await,
+    }
+}
+
+fn builtin_profiles(namespace: &Option<String>) -> Vec<Profile> {
+    let mut profiles = vec![admin_profile(), cluster_readonly_profile()];
+    if let Some(namespace) = namespace {
+        profiles.push(namespaced_readonly(namespace));
+    }
+
+    profiles
+}
+
+/// Runs the suite against the cluster, any failed check fails the command
+async fn test(arguments: ProfileTestArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let suite = match &arguments.suite {
+        Some(path) => Suite::read(path).await?,
+        None => Suite::from_profiles(&builtin_profiles(&arguments.namespace)),
+    };
+
+    let results = suite.run(&client).await?;
+    // With --output json, stdout gets the json report unless it goes to a file
+    let format = match &arguments.report {
+        None if output::json() => ReportFormat::Json,
+        _ => arguments.format,
+    };
+    let report = match format {
+        ReportFormat::Text => suite::render_text(&results),
+        ReportFormat::Json => suite::render_json(&results),
+        ReportFormat::Junit => suite::render_junit(&results),
+    };
+
+    match &arguments.report {
+        Some(path) => tokio::fs::write(path, report).await?,
+        None => print!("{}", report),
+    }
+
+    let failed = results.iter().filter(|result| !result.passed).count();
+    if failed > 0 {
+        return Err(CoralGateError::ProfileTestFailed(failed));
+    }
+
+    Ok(())
+}
+
+/// Lints every profile, unacknowledged high risk findings fail the command
+async fn lint(arguments: ProfileLintArgs) -> Result<()> {
+    if arguments.json {
+        tracing::warn!("--json is deprecated, use --output json");
+    }
+
+    // Namespaced profiles bind the same roles in every namespace
+    let namespace = arguments.namespace.as_deref().unwrap_or("default");
+    let mut profiles = vec![
+        admin_profile(),
+        cluster_readonly_profile(),
+        namespaced_readonly(namespace),
+        namespaced_edit(namespace),
+        namespaced_admin(namespace),
+    ];
+    for path in &arguments.profile_file {
+        profiles.push(ProfileDefinition::read(path).await?.into_profile());
+    }
+
+    let snapshot = match &arguments.snapshot {
+        Some(path) => Snapshot::read(path).await?,
+        None => {
+            let mut client_manager = ClientManager::default();
+            let client = client_manager
+                .generate_kube_client(&arguments.kubeconfig)
+                .await?;
+
+            Snapshot::fetch(&client).await?
+        }
+    };
+
+    let findings: Vec<lint::Finding> = profiles
+        .iter()
+        .flat_map(|profile| lint::lint_profile(profile, &snapshot))
+        .collect();
+
+    if arguments.json || output::json() {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&findings).unwrap_or_default()
+        );
+    } else {
+        for finding in &findings {
+            let acknowledged = if finding.acknowledged {
+                " (acknowledged)"
+            } else {
+                ""
+            };
+
+            say!(
+                "[{}] {}: {} in {}{}\n    {}\n    {}",
+                finding.severity,
+                finding.profile,
+                finding.id,
+                finding.source,
+                acknowledged,
+                finding.rule,
+                finding.explanation
+            );
+        }
+    }
+
+    if let Some(finding) = lint::blocking(&findings).first() {
+        return Err(CoralGateError::HighRiskProfile {
+            profile: finding.profile.clone(),
+            findings: lint::blocking(&findings)
+                .iter()
+                .filter(|other| other.profile == finding.profile)
+                .map(|other| other.id)
+                .collect::<Vec<_>>()
+                .join(", "),
+        });
+    }
+
+    Ok(())
+}
+
+/// Writes the profile covering what the identity used, then lists what it is
+/// granted today but never used
+async fn synthesize(arguments: ProfileSynthesizeArgs) -> Result<()> {
+    let filter = AuditFilter {
+        user: arguments.user.clone(),
+        group: arguments.group.clone(),
+        since: arguments.since,
+        until: arguments.until,
+    };
+
+    let events = audit::read_events(&arguments.audit_log, &filter).await?;
+    let usage = Usage::from_events(&events);
+
+    let subject = arguments
+        .user
+        .clone()
+        .or(arguments.group.clone())
+        .unwrap_or_default();
+    let name = arguments
+        .name
+        .clone()
+        .unwrap_or(shared::sanitize_name(&subject));
+
+    let definition = synthesize::synthesize(&name, arguments.group.clone(), &usage);
+    let yaml = serde_yaml::to_string(&definition).unwrap_or_default();
+
+    match &arguments.out {
+        Some(path) => tokio::fs::write(path, yaml).await?,
+        None if output::json() => output::result(&definition),
+        None => {
+            output::take_stdout();
+            print!("{}", yaml)
+        }
+    }
+
+    say!(
+        "{} requests of {} matched, {} distinct",
+        events.len(),
+        subject,
+        usage.requests.len() + usage.non_resource.len()
+    );
+
+    if arguments.skip_compare {
+        return Ok(());
+    }
+
+    let snapshot = match &arguments.snapshot {
+        Some(path) => Snapshot::read(path).await?,
+        None => {
+            let mut client_manager = ClientManager::default();
+            let client = client_manager
+                .generate_kube_client(&arguments.kubeconfig)
+                .await?;
+
+            Snapshot::fetch(&client).await?
+        }
+    };
+
+    // The log knows which groups the identity authenticated with
+    let mut groups: Vec<String> = events
+        .iter()
+        .flat_map(|event| event.effective_user().groups.clone())
+        .chain(arguments.group.clone())
+        .chain([rbac::AUTHENTICATED_GROUP.into()])
+        .collect();
+    groups.sort();
+    groups.dedup();
+
+    let user = arguments.user.clone().unwrap_or_default();
+    let unused = synthesize::unused(&snapshot.effective_rules(&user, &groups), &usage);
+
+    if unused.is_empty() {
+        say!("every granted permission was used");
+    } else {
+        say!("granted but unused:");
+        for line in unused {
+            say!("  {}", line);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use crate::{
+    command::structure::RefreshCaArgs,
+    core::{ca, certificate, client::ClientManager},
+    error::*,
+    output::{self, say},
+};
+
+use std::path::{Path, PathBuf};
+
+use kube::config::Kubeconfig;
+use serde::Serialize;
+use tokio::fs;
+
+/// What happened to one kubeconfig
+#[derive(Serialize)]
+struct Outcome {
+    path: String,
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// Writes the cluster's current CA bundle into issued kubeconfigs, and reports
+/// client certificates the bundle no longer trusts as `untrusted`. The bundle
+/// is the one the API server serves with, on clusters signing client
+/// certificates with another CA the check is not reliable
+pub async fn handle(arguments: RefreshCaArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    // The published bundle carries the old and the new CA while a rotation is underway
+    let (roots, source) = match &arguments.ca_file {
+        Some(path) => (ca::read_bundle(path).await?, path.clone()),
+        None => match ca::published(&client).await {
+            Some(published) => published,
+            None => (
+                client_manager.get_root_cert()?.clone(),
+                "the kubeconfig".into(),
+            ),
+        },
+    };
+    ca::check_presented(&client_manager.cluster_url()?, &roots, &source).await;
+    say!("using {} CA certificates from {}", roots.len(), source);
+
+    client_manager.set_root_cert(roots.clone());
+    let bundle = client_manager.root_cert_base64()?;
+    let server = client_manager.cluster_url()?;
+
+    let mut outcomes = Vec::new();
+    for path in kubeconfig_files(Path::new(&arguments.target)).await? {
+        let outcome = refresh(&path, &server, &bundle, &roots, arguments.dry_run).await;
+
+        match &outcome.detail {
+            Some(detail) => say!("{:<12} {} ({})", outcome.result, outcome.path, detail),
+            None => say!("{:<12} {}", outcome.result, outcome.path),
+        }
+        outcomes.push(outcome);
+    }
+    output::result(&outcomes);
+
+    let count = |result| {
+        outcomes
+            .iter()
+            .filter(|outcome| outcome.result == result)
+            .count()
+    };
+    match (count("failed"), count("untrusted")) {
+        (0, 0) => {}
+        (0, untrusted) => return Err(CoralGateError::UntrustedCertificates(untrusted)),
+        (failures, _) => return Err(CoralGateError::RefreshFailed(failures)),
+    }
+
+    Ok(())
+}
+
+/// The target itself, or the kubeconfigs `generate --roster` writes into a
+/// directory. Encrypted `.age` files can not be refreshed and are left out
+async fn kubeconfig_files(target: &Path) -> Result<Vec<PathBuf>> {
+    if !fs::metadata(target).await?.is_dir() {
+        return Ok(vec![target.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(target).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_kubeconfig = path
+            .extension()
+            .is_some_and(|extension| extension == "kubeconfig")
+            || path.file_name().is_some_and(|name| name == "kubeconfig");
+
+        if is_kubeconfig && entry.file_type().await?.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+async fn refresh(
+    path: &Path,
+    server: &str,
+    bundle: &str,
+    roots: &[Vec<u8>],
+    dry_run: bool,
+) -> Outcome {
+    let outcome = |result, detail| Outcome {
+        path: path.display().to_string(),
+        result,
+        detail,
+    };
+
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(error) => return outcome("skipped", Some(error.to_string())),
+    };
+
+    // Renewing the CA does not help a certificate signed by a CA that is gone
+    let untrusted = match Kubeconfig::from_yaml(&content) {
+        Ok(kubeconfig) => match certificate::client_certificate_chain(&kubeconfig).await {
+            Ok(chain) => certificate::verify_chain(&chain[0], &chain[1..], roots)
+                .err()
+                .map(|error| format!("{}, issue it again", error)),
+            Err(_) => None,
+        },
+        Err(error) => return outcome("skipped", Some(error.to_string())),
+    };
+
+    let refreshed = match ca::replace_bundle(&content, server, bundle) {
+        Ok(refreshed) => refreshed,
+        Err(error) => return outcome("skipped", Some(error)),
+    };
+
+    let result = match refreshed {
+        None => "unchanged",
+        Some(_) if dry_run => "would update",
+        Some(refreshed) => match fs::write(path, refreshed).await {
+            Ok(()) => "updated",
+            Err(error) => return outcome("failed", Some(error.to_string())),
+        },
+    };
+
+    match untrusted {
+        Some(reason) => outcome("untrusted", Some(format!("{}, {}", result, reason))),
+        None => outcome(result, None),
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{
+    command::structure::{ReportAccessArgs, ReportArgs, ReportCommands, TableFormat},
+    core::{client::ClientManager, ledger, rbac::Snapshot, report},
+    error::*,
+    output,
+};
+
+pub async fn handle(arguments: ReportArgs) -> Result<()> {
+    match arguments.command {
+        ReportCommands::Access(access_arguments) => access(access_arguments).await,
+    }
+}
+
+/// Who has access to the cluster, from the ledger and the RBAC bindings
+async fn access(arguments: ReportAccessArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let grants = ledger::list_with_csrs(&client, None).await?;
+    let snapshot = Snapshot::fetch(&client).await?;
+
+    let entries = report::access(&snapshot, &grants, arguments.include_external);
+    // With --output json, stdout gets the json report unless it goes to a file
+    let format = match &arguments.report {
+        None if output::json() => TableFormat::Json,
+        _ => arguments.format,
+    };
+    let report = match format {
+        TableFormat::Markdown => report::render_markdown(&entries),
+        TableFormat::Csv => report::render_csv(&entries),
+        TableFormat::Json => report::render_json(&entries),
+    };
+
+    match &arguments.report {
+        Some(path) => tokio::fs::write(path, report).await?,
+        None => print!("{}", report),
+    }
+
+    Ok(())
+}
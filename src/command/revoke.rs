@@ -0,0 +1,101 @@
+use crate::{
+    command::structure::RevokeArgs,
+    core::{
+        client::ClientManager,
+        inventory, ledger,
+        revocation::{self, Revocation},
+        rollback,
+    },
+    error::*,
+    output::{self, say},
+};
+
+/// Deletes the user's own bindings and puts the user on the admission denylist
+/// until the last certificate issued to it expires
+pub async fn handle(arguments: RevokeArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    if arguments.list {
+        let revocations = revocation::list(&client).await?;
+        if output::json() {
+            output::result(&revocations);
+            return Ok(());
+        }
+
+        for revocation in revocations {
+            match revocation.expires_at {
+                Some(expires_at) => {
+                    println!("{} until {}", revocation.user, expires_at.to_rfc3339())
+                }
+                None => println!("{} until unrevoked", revocation.user),
+            }
+        }
+
+        return Ok(());
+    }
+
+    let grants = ledger::list_with_csrs(&client, None).await?;
+    let user = match (&arguments.user, &arguments.serial) {
+        (Some(user), _) => user.clone(),
+        (None, Some(serial)) => grants
+            .iter()
+            .find(|grant| &grant.serial == serial)
+            .map(|grant| grant.user.clone())
+            .ok_or(CoralGateError::UnknownSerial(serial.clone()))?,
+        (None, None) => return Ok(()),
+    };
+
+    let expires_at = grants
+        .iter()
+        .filter(|grant| grant.user == user && grant.is_active())
+        .map(|grant| grant.expires_at)
+        .max();
+
+    ledger::ensure_namespace(&client).await?;
+    revocation::ensure_policy(&client).await?;
+    let pruned = revocation::revoke(
+        &client,
+        &Revocation {
+            user: user.clone(),
+            expires_at,
+        },
+    )
+    .await?;
+    // The revocation is in place, failing to remove bindings must not lift it
+    rollback::commit();
+
+    let mut removed = Vec::new();
+    for binding in inventory::user_bindings(&client, &user).await? {
+        inventory::delete(&client, &binding).await?;
+        say!("removed {}", binding);
+        removed.push(binding.to_string());
+    }
+
+    match expires_at {
+        Some(expires_at) => say!(
+            "{} is revoked until its last certificate expires at {}",
+            user,
+            expires_at.to_rfc3339()
+        ),
+        None => say!(
+            "{} is revoked until unrevoked, no active certificate is known",
+            user
+        ),
+    }
+
+    for user in &pruned {
+        say!("pruned {}, its certificates expired", user);
+    }
+
+    output::result(&serde_json::json!({
+        "user": user,
+        "expiresAt": expires_at,
+        "removed": removed,
+        "prunedRevocations": pruned,
+    }));
+
+    Ok(())
+}
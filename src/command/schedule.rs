@@ -0,0 +1,155 @@
+use crate::{
+    command::structure::{
+        ScheduleArgs, ScheduleCommands, ScheduleImportArgs, ScheduleListArgs, ScheduleReconcileArgs,
+    },
+    core::{
+        client::ClientManager,
+        ical, profile, rollback,
+        schedule::{self, Schedule},
+    },
+    error::*,
+    output::{self, say},
+    shared,
+};
+
+use chrono::Utc;
+use std::collections::BTreeMap;
+use tracing::Instrument;
+
+pub async fn handle(arguments: ScheduleArgs) -> Result<()> {
+    match arguments.command {
+        ScheduleCommands::Import(import_arguments) => import(import_arguments).await,
+        ScheduleCommands::Reconcile(reconcile_arguments) => reconcile(reconcile_arguments).await,
+        ScheduleCommands::List(list_arguments) => list(list_arguments).await,
+    }
+}
+
+/// Reads the shifts and stores them, bindings follow on the next reconcile
+async fn import(arguments: ScheduleImportArgs) -> Result<()> {
+    // Fails early on unknown profiles or a missing namespace
+    profile::resolve(&arguments.profile, arguments.namespace.as_deref())?;
+
+    let content = tokio::fs::read_to_string(&arguments.file).await?;
+    let now = Utc::now();
+    let until = shared::from_now(arguments.horizon)?;
+    let events = ical::parse(&content)
+        .and_then(|events| ical::expand(events, now, until))
+        .map_err(|reason| CoralGateError::InvalidCalendar {
+            path: arguments.file.clone(),
+            reason,
+        })?;
+    let (shifts, skipped) = schedule::shifts(&events, arguments.user_from);
+
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let schedule = Schedule {
+        name: schedule::schedule_name(&arguments.profile, arguments.namespace.as_deref()),
+        profile: arguments.profile.clone(),
+        namespace: arguments.namespace.clone(),
+        source: arguments.file.clone(),
+        imported_at: now,
+        shifts,
+    };
+    schedule::save(&client, &schedule).await?;
+
+    say!(
+        "imported {} shifts into {}",
+        schedule.shifts.len(),
+        schedule.name
+    );
+    if skipped > 0 {
+        say!(
+            "skipped {} events that have no start and end or name no user",
+            skipped
+        );
+    }
+
+    output::result(&schedule);
+
+    Ok(())
+}
+
+async fn reconcile(arguments: ScheduleReconcileArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    loop {
+        let mut pass = BTreeMap::new();
+        for schedule in schedule::list(&client).await? {
+            let reconciled = schedule::reconcile(&client, &schedule)
+                .instrument(tracing::info_span!("reconcile", schedule = %schedule.name))
+                .await?;
+
+            for bound in &reconciled.bound {
+                say!("{}: on shift {}", schedule.name, bound);
+            }
+            for removed in &reconciled.removed {
+                say!("{}: removed {}", schedule.name, removed);
+            }
+            pass.insert(schedule.name, reconciled);
+        }
+        output::result(&pass);
+
+        // A cancelled later run must not remove bindings of this one
+        rollback::commit();
+
+        let Some(every) = arguments.every else {
+            return Ok(());
+        };
+        tokio::time::sleep(every.to_std().unwrap_or_default()).await;
+    }
+}
+
+async fn list(arguments: ScheduleListArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let now = Utc::now();
+    let mut schedules = schedule::list(&client).await?;
+    schedules.retain(|schedule| {
+        arguments
+            .namespace
+            .as_ref()
+            .is_none_or(|namespace| schedule.namespace.as_ref() == Some(namespace))
+    });
+    for schedule in &mut schedules {
+        schedule
+            .shifts
+            .retain(|shift| arguments.all || shift.state(now) != "ended");
+    }
+
+    if output::json() {
+        output::result(&schedules);
+        return Ok(());
+    }
+
+    for schedule in &schedules {
+        println!(
+            "{} ({} in {}, from {})",
+            schedule.name,
+            schedule.profile,
+            schedule.namespace.as_deref().unwrap_or("the cluster"),
+            schedule.source
+        );
+
+        for shift in &schedule.shifts {
+            let state = shift.state(now);
+            println!(
+                "  {:<9} {:<24} {} - {}",
+                state,
+                shift.user,
+                shift.start.to_rfc3339(),
+                shift.end.to_rfc3339()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -2,23 +2,112 @@ use crate::{
     command::structure::SetupArgs,
     core::{
         client::ClientManager,
-        profile::{admin_profile, cluster_readonly_profile},
+        lint,
+        profile::{
+            ApplyOptions, Change, Profile, ProfileDefinition, admin_profile,
+            cluster_readonly_profile,
+        },
+        rbac::Snapshot,
+        rollback,
     },
     error::*,
+    output::{self, say},
 };
 
 /// We will apply default roles and rolebindings
 pub async fn handle(arguments: SetupArgs) -> Result<()> {
     let mut client_manager = ClientManager::default();
-    let admin = admin_profile();
-    let cluster_readonly = cluster_readonly_profile();
+    let mut profiles = vec![admin_profile(), cluster_readonly_profile()];
+    for path in &arguments.profile_file {
+        profiles.push(ProfileDefinition::read(path).await?.into_profile());
+    }
 
     let client = client_manager
         .generate_kube_client(&arguments.kubeconfig)
         .await?;
 
-    admin.apply(&client).await?;
-    cluster_readonly.apply(&client).await?;
+    let snapshot = Snapshot::fetch(&client).await?;
+    for profile in &profiles {
+        refuse_high_risk(profile, &snapshot)?;
+    }
+
+    if arguments.dry_run {
+        let mut diffs = Vec::new();
+        for profile in &profiles {
+            diffs.extend(print_diff(profile, &client).await?);
+        }
+        output::result(&diffs);
+        return Ok(());
+    }
+
+    let options = ApplyOptions {
+        adopt: arguments.adopt,
+    };
+
+    for profile in &profiles {
+        profile.apply(&client, &options).await?;
+        // A later profile failing must not remove the ones already installed
+        rollback::commit();
+    }
+
+    let applied: Vec<&str> = profiles.iter().map(|profile| profile.name()).collect();
+    output::result(&serde_json::json!({ "applied": applied }));
 
     Ok(())
 }
+
+/// High risk lint findings must be acknowledged in the profile definition
+fn refuse_high_risk(profile: &Profile, snapshot: &Snapshot) -> Result<()> {
+    let findings = lint::lint_profile(profile, snapshot);
+    let blocking = lint::blocking(&findings);
+
+    if blocking.is_empty() {
+        return Ok(());
+    }
+
+    Err(CoralGateError::HighRiskProfile {
+        profile: profile.name().into(),
+        findings: blocking
+            .iter()
+            .map(|finding| finding.id)
+            .collect::<Vec<_>>()
+            .join(", "),
+    })
+}
+
+/// Prints what setup would change, returned for `--output json`
+async fn print_diff(profile: &Profile, client: &kube::Client) -> Result<Vec<serde_json::Value>> {
+    let mut diffs = Vec::new();
+    for diff in profile.diff(client).await? {
+        let (change, details) = match &diff.change {
+            Change::Create => ("create", vec![]),
+            Change::Unchanged => ("unchanged", vec![]),
+            Change::NotOwned => ("not-owned", vec![]),
+            Change::Update(changes) => ("update", changes.clone()),
+        };
+        diffs.push(serde_json::json!({
+            "profile": profile.name(),
+            "resource": diff.resource,
+            "change": change,
+            "details": details,
+        }));
+
+        match diff.change {
+            Change::Create => say!("{}: {} will be created", profile.name(), diff.resource),
+            Change::Unchanged => say!("{}: {} is up to date", profile.name(), diff.resource),
+            Change::NotOwned => say!(
+                "{}: {} exists but is not owned by coralgate, needs --adopt",
+                profile.name(),
+                diff.resource
+            ),
+            Change::Update(changes) => {
+                say!("{}: {} will be updated", profile.name(), diff.resource);
+                for change in changes {
+                    say!("    {}", change);
+                }
+            }
+        }
+    }
+
+    Ok(diffs)
+}
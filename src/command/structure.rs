@@ -1,5 +1,8 @@
 use clap::{Parser, Subcommand};
 
+use crate::logging::LogFormat;
+use crate::output::OutputFormat;
+
 // Constants
 const DEFAULT_KUBECONFIG: &str = "~/.kube/config";
 const DEFAULT_VALIDITY_HOURS: i32 = 24 * 30;
@@ -35,6 +38,23 @@ macro_rules! define_args {
 #[derive(Parser, Debug, Clone)]
 #[command(version, about = "This is a package for you to create temporary safe kubeconfigs", long_about = None)]
 pub struct Cli {
+    /// Print results and errors as text or JSON
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Log what coralgate does on stderr, repeat for more detail
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Format of the logs
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Export traces to an OTLP/HTTP collector, e.g. http://localhost:4318/v1/traces
+    #[cfg(feature = "otlp")]
+    #[arg(long, global = true, env = "CORALGATE_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -47,6 +67,162 @@ pub enum Commands {
     /// Setups predefined roles and role bindings used to issue kubeconfig.
     /// This needs admin access
     Setup(SetupArgs),
+
+    /// Removes every role, binding and CSR created by coralgate.
+    /// This needs admin access
+    Teardown(TeardownArgs),
+
+    /// Works with the permission profiles
+    Profile(ProfileArgs),
+
+    /// Shows the effective permissions of a user and which binding grants them
+    Explain(ExplainArgs),
+
+    /// Reports for access reviews
+    Report(ReportArgs),
+
+    /// Summarizes what coralgate issued identities did, from an audit log
+    Activity(ActivityArgs),
+
+    /// Cuts a user off before the certificates expire, mutating requests are
+    /// rejected by an admission policy
+    Revoke(RevokeArgs),
+
+    /// Lifts a revocation
+    Unrevoke(UnrevokeArgs),
+
+    /// Lists the grants recorded in the ledger
+    List(ListArgs),
+
+    /// Issues a short lived admin kubeconfig for emergencies, loudly
+    BreakGlass(BreakGlassArgs),
+
+    /// Binds a profile to an existing user or group for a limited time
+    Elevate(ElevateArgs),
+
+    /// Removes expired temporary bindings and revocations
+    Gc(GcArgs),
+
+    /// Grants that follow an on-call schedule
+    Schedule(ScheduleArgs),
+
+    /// Checks the cluster and the kubeconfig's permissions before issuing
+    Doctor(DoctorArgs),
+
+    /// Replaces the CA bundle in issued kubeconfigs after a CA rotation, and
+    /// reports client certificates that do not chain to that server CA bundle
+    /// as untrusted
+    RefreshCa(RefreshCaArgs),
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ScheduleArgs {
+    #[command(subcommand)]
+    pub command: ScheduleCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ScheduleCommands {
+    /// Stores the shifts of an iCalendar file, replacing the profile's previous import
+    Import(ScheduleImportArgs),
+
+    /// Binds everyone on shift and unbinds everyone whose shift ended, run it periodically
+    Reconcile(ScheduleReconcileArgs),
+
+    /// Shows imported shifts and whether they are active
+    List(ScheduleListArgs),
+}
+
+/// Where the user of a shift is taken from
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ShiftUser {
+    /// The event title
+    #[default]
+    Summary,
+    /// The address of the first attendee
+    Attendee,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReportArgs {
+    #[command(subcommand)]
+    pub command: ReportCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ReportCommands {
+    /// Lists everyone with access to the cluster
+    Access(ReportAccessArgs),
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Default)]
+pub enum TableFormat {
+    #[default]
+    Markdown,
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ProfileArgs {
+    #[command(subcommand)]
+    pub command: ProfileCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ProfileCommands {
+    /// Checks the profiles grant what a suite expects, using SubjectAccessReview
+    Test(ProfileTestArgs),
+
+    /// Flags privilege escalation paths the profiles open
+    Lint(ProfileLintArgs),
+
+    /// Builds a least privilege profile from what an identity did in an audit log
+    Synthesize(ProfileSynthesizeArgs),
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Default)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    Json,
+    Junit,
+}
+
+/// How an issued certificate gets its permissions
+#[derive(
+    clap::ValueEnum,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BindingMode {
+    /// The certificate carries the profile group, bound once for everyone
+    #[default]
+    Group,
+    /// Bindings are created for the certificate's user alone
+    User,
+}
+
+impl BindingMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BindingMode::Group => "group",
+            BindingMode::User => "user",
+        }
+    }
+}
+
+impl std::fmt::Display for BindingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.as_str())
+    }
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -65,24 +241,431 @@ impl PermissionProfile {
 }
 
 define_args! {
-    pub struct SetupArgs { }
+    pub struct SetupArgs {
+        /// Only show what would change in the cluster
+        #[arg(long)]
+        pub dry_run: bool,
+
+        /// Take over existing objects that were not created by coralgate
+        #[arg(long)]
+        pub adopt: bool,
+
+        /// Also apply profiles defined in YAML, e.g. by `profile synthesize`
+        #[arg(long)]
+        pub profile_file: Vec<String>,
+    }
 }
 
 define_args! {
-    pub struct GenerateArgs {
-        /// Username to create
+    pub struct TeardownArgs {
+        /// Only remove resources of this profile: admin, cluster-readonly,
+        /// namespaced-readonly, namespaced-edit or namespaced-admin with -n, or
+        /// the name of a profile applied with --profile-file
+        #[arg(short, long)]
+        pub profile: Option<String>,
+
+        /// Keep bindings that still back unexpired certificates
+        #[arg(long)]
+        pub keep_active_grants: bool,
+
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        pub yes: bool,
+    }
+}
+
+define_args! {
+    pub struct ProfileTestArgs {
+        /// Suite file with allow/deny expectations, the built-in suite when omitted
+        pub suite: Option<String>,
+
+        /// Report format
+        #[arg(short, long, value_enum, default_value_t = ReportFormat::Text)]
+        pub format: ReportFormat,
+
+        /// Write the report to a file instead of stdout
+        #[arg(short, long)]
+        pub report: Option<String>,
+    }
+}
+
+define_args! {
+    pub struct ProfileLintArgs {
+        /// Read referenced ClusterRoles from a dump instead of the cluster
+        #[arg(long)]
+        pub snapshot: Option<String>,
+
+        /// Also lint profiles defined in YAML, e.g. by `profile synthesize`
+        #[arg(long)]
+        pub profile_file: Vec<String>,
+
+        /// Deprecated, use --output json
+        #[arg(long, hide = true)]
+        pub json: bool,
+    }
+}
+
+define_args! {
+    pub struct ProfileSynthesizeArgs {
+        /// Apiserver audit log, one JSON event per line
+        pub audit_log: String,
+
+        /// User whose requests are used
+        #[arg(short, long, required_unless_present = "group")]
+        pub user: Option<String>,
+
+        /// Group whose members' requests are used
+        #[arg(short, long)]
+        pub group: Option<String>,
+
+        /// Ignore requests before this time (RFC 3339)
+        #[arg(long)]
+        pub since: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Ignore requests after this time (RFC 3339)
+        #[arg(long)]
+        pub until: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Name of the profile, derived from the user or group when omitted
+        #[arg(long)]
+        pub name: Option<String>,
+
+        /// Write the profile to a file instead of stdout
+        #[arg(short, long)]
+        pub out: Option<String>,
+
+        /// Read RBAC objects from a dump instead of the cluster
+        #[arg(long)]
+        pub snapshot: Option<String>,
+
+        /// Do not compare against the permissions granted today
+        #[arg(long, conflicts_with = "snapshot")]
+        pub skip_compare: bool,
+    }
+}
+
+define_args! {
+    pub struct ActivityArgs {
+        /// Apiserver audit log, one JSON event per line
+        #[arg(long)]
+        pub audit_log: String,
+
+        /// Only this user, every user in the ledger when omitted
+        #[arg(short, long)]
+        pub user: Option<String>,
+
+        /// Ignore requests before this time (RFC 3339)
+        #[arg(long)]
+        pub since: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Ignore requests after this time (RFC 3339)
+        #[arg(long)]
+        pub until: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Deprecated, use --output json
+        #[arg(long, hide = true)]
+        pub json: bool,
+
+        /// Write the report to a file instead of stdout
+        #[arg(short, long)]
+        pub report: Option<String>,
+    }
+}
+
+define_args! {
+    pub struct RevokeArgs {
+        /// User to revoke
+        #[arg(short, long, required_unless_present_any = ["serial", "list"])]
+        pub user: Option<String>,
+
+        /// Revoke the user a certificate was issued to, by serial from the ledger.
+        /// The admission policy only sees usernames, so every certificate of that user is revoked
+        #[arg(long, conflicts_with = "user")]
+        pub serial: Option<String>,
+
+        /// Show revoked users instead
+        #[arg(long, conflicts_with_all = ["user", "serial"])]
+        pub list: bool,
+    }
+}
+
+define_args! {
+    pub struct UnrevokeArgs {
+        /// User to restore
         #[arg(short, long)]
         pub user: String,
+    }
+}
+
+define_args! {
+    pub struct ListArgs {
+        /// Only grants of this user
+        #[arg(short, long)]
+        pub user: Option<String>,
+
+        /// Include expired grants
+        #[arg(short, long)]
+        pub all: bool,
+    }
+}
+
+define_args! {
+    pub struct ElevateArgs {
+        /// User to elevate
+        #[arg(short, long, required_unless_present = "group")]
+        pub user: Option<String>,
+
+        /// Group to elevate instead of a user
+        #[arg(short, long, conflicts_with = "user")]
+        pub group: Option<String>,
+
+        /// Profile to bind: admin, cluster-readonly, namespaced-readonly, namespaced-edit
+        /// or namespaced-admin
+        #[arg(short, long)]
+        pub profile: String,
+
+        /// How long the binding lasts
+        #[arg(long = "for", value_parser = crate::shared::parse_duration)]
+        pub duration: chrono::Duration,
+
+        /// Why the access is needed, kept on the binding
+        #[arg(short, long)]
+        pub reason: Option<String>,
+
+        /// Wait and remove the binding when the time is up, otherwise `gc` does
+        #[arg(short, long)]
+        pub wait: bool,
+    }
+}
+
+define_args! {
+    pub struct DoctorArgs {
+        /// Signer whose approval permission is checked
+        #[arg(long, default_value = crate::core::csr::DEFAULT_SIGNER)]
+        pub signer_name: String,
+
+        /// Also check profiles defined in YAML are installed
+        #[arg(long)]
+        pub profile_file: Vec<String>,
+    }
+}
+
+define_args! {
+    pub struct RefreshCaArgs {
+        /// Issued kubeconfig, or a directory of `*.kubeconfig` files
+        pub target: String,
+
+        /// CA bundle to write instead of the one the cluster publishes
+        #[arg(long)]
+        pub ca_file: Option<String>,
+
+        /// Only show which kubeconfigs would change
+        #[arg(long)]
+        pub dry_run: bool,
+    }
+}
+
+define_args! {
+    pub struct GcArgs {
+        /// Only show what would be removed
+        #[arg(long)]
+        pub dry_run: bool,
+    }
+}
+
+define_args! {
+    pub struct ScheduleImportArgs {
+        /// iCalendar file with one event per shift
+        pub file: String,
+
+        /// Profile the users get during their shifts
+        #[arg(short, long)]
+        pub profile: String,
+
+        /// Where the user of a shift is taken from
+        #[arg(long, value_enum, default_value_t = ShiftUser::Summary)]
+        pub user_from: ShiftUser,
+
+        /// How far ahead recurring events are expanded, import again before it runs out
+        #[arg(long, default_value = "90d", value_parser = crate::shared::parse_duration)]
+        pub horizon: chrono::Duration,
+    }
+}
+
+define_args! {
+    pub struct ScheduleReconcileArgs {
+        /// Keep reconciling at this interval instead of once, e.g. 5m
+        #[arg(long, value_parser = crate::shared::parse_duration)]
+        pub every: Option<chrono::Duration>,
+    }
+}
+
+define_args! {
+    pub struct ScheduleListArgs {
+        /// Include shifts that ended
+        #[arg(short, long)]
+        pub all: bool,
+    }
+}
+
+define_args! {
+    pub struct ExplainArgs {
+        /// User to explain, taken from --inspect when omitted
+        #[arg(short, long, required_unless_present = "inspect")]
+        pub user: Option<String>,
+
+        /// Groups of the user, looked up in the ledger when omitted
+        #[arg(short, long)]
+        pub group: Vec<String>,
+
+        /// Take user and groups from the client certificate of this kubeconfig
+        #[arg(long)]
+        pub inspect: Option<String>,
+
+        /// Read RBAC objects from a dump instead of the cluster
+        #[arg(long)]
+        pub snapshot: Option<String>,
+
+        /// Write the cluster's RBAC objects to this file for offline use
+        #[arg(long, conflicts_with = "snapshot")]
+        pub dump_snapshot: Option<String>,
+    }
+}
+
+define_args! {
+    pub struct ReportAccessArgs {
+        /// Report format
+        #[arg(short, long, value_enum, default_value_t = TableFormat::Markdown)]
+        pub format: TableFormat,
+
+        /// Also list subjects bound outside coralgate
+        #[arg(long)]
+        pub include_external: bool,
+
+        /// Write the report to a file instead of stdout
+        #[arg(short, long)]
+        pub report: Option<String>,
+    }
+}
 
-        #[arg(short, long, default_value = "./kubeconfig")]
-        output: String,
+define_args! {
+    pub struct GenerateArgs {
+        /// Username to create
+        #[arg(short, long, required_unless_present = "roster", conflicts_with = "roster")]
+        pub user: Option<String>,
+
+        /// Where to write the kubeconfig
+        #[arg(short, long, default_value = "./kubeconfig", conflicts_with = "roster")]
+        pub out: String,
 
         /// How long the kubeconfig should be valid (in hours)
         #[arg(short, long, default_value_t = DEFAULT_VALIDITY_HOURS)]
         pub expire: i32,
 
         /// Predefined policies (Admin, Readonly)
+        #[arg(short, long, required_unless_present = "roster", conflicts_with = "roster")]
+        pub profile: Option<PermissionProfile>,
+
+        /// Do not test the issued kubeconfig against the cluster
+        #[arg(long)]
+        pub skip_verify: bool,
+
+        /// Bind the profile to the user alone, so access can be removed per person
+        #[arg(long, value_enum, default_value_t = BindingMode::Group)]
+        pub binding_mode: BindingMode,
+
+        /// Why the access is needed, kept on the CSR and in the ledger
         #[arg(short, long)]
-        pub profile: PermissionProfile,
+        pub reason: Option<String>,
+
+        /// YAML or CSV file listing users to issue for, see the README for its columns
+        #[arg(long)]
+        pub roster: Option<String>,
+
+        /// Directory the roster's kubeconfigs are written to, one per user
+        #[arg(long, default_value = "./kubeconfigs", conflicts_with = "user")]
+        pub output_dir: String,
+
+        /// How many roster users are issued at the same time
+        #[arg(long, default_value_t = 4, conflicts_with = "user")]
+        pub concurrency: usize,
+
+        /// Issue again for roster users who still hold an active grant of the profile
+        #[arg(long, conflicts_with = "user")]
+        pub force: bool,
+
+        /// CA certificate to sign with locally instead of through the certificates api
+        #[arg(long, requires = "ca_key", conflicts_with = "roster")]
+        pub ca_cert: Option<String>,
+
+        /// Private key of --ca-cert
+        #[arg(long, requires = "ca_cert")]
+        pub ca_key: Option<String>,
+
+        /// API server URL written to the kubeconfig when signing locally,
+        /// taken from --kubeconfig when missing
+        #[arg(long, requires = "ca_cert")]
+        pub server: Option<String>,
+
+        /// Cluster CA bundle to embed in the kubeconfig, found from the
+        /// kubeconfig or the cluster when missing
+        #[arg(long, conflicts_with = "ca_cert")]
+        pub ca_file: Option<String>,
+
+        /// Signer the CSR is addressed to, e.g. one run by cert-manager
+        #[arg(long, default_value = crate::core::csr::DEFAULT_SIGNER, conflicts_with = "ca_cert")]
+        pub signer_name: String,
+
+        /// Comma separated usages to request, checked on the returned certificate
+        #[arg(long, value_delimiter = ',', default_value = crate::core::csr::DEFAULT_USAGE, conflicts_with = "ca_cert")]
+        pub usages: Vec<String>,
+
+        /// Leave approval to the signer's own approver instead of approving the CSR
+        #[arg(long, conflicts_with = "ca_cert")]
+        pub no_approve: bool,
+
+        /// How long to wait for the certificate, e.g. 30m when a person or a
+        /// queue approves the CSR
+        #[arg(long, default_value = crate::core::csr::DEFAULT_WAIT, value_parser = crate::shared::parse_duration, conflicts_with = "ca_cert")]
+        pub wait: chrono::Duration,
+
+        /// Keep the CSR this long after issuing, e.g. 1d, for `gc` to remove later.
+        /// By default it is deleted once the grant is in the ledger
+        #[arg(long, value_parser = crate::shared::parse_duration, conflicts_with = "ca_cert")]
+        pub csr_retention: Option<chrono::Duration>,
+    }
+}
+
+define_args! {
+    pub struct BreakGlassArgs {
+        /// User who needs emergency access
+        #[arg(short, long)]
+        pub user: String,
+
+        /// Justification, e.g. the incident id
+        #[arg(short, long)]
+        pub reason: String,
+
+        /// How long the credential is valid, at least 10m
+        #[arg(short, long, default_value = "30m", value_parser = crate::shared::parse_duration)]
+        pub duration: chrono::Duration,
+
+        /// Longest duration allowed, longer requests are capped
+        #[arg(long, env = "CORALGATE_BREAK_GLASS_MAX_DURATION", default_value = "1h", value_parser = crate::shared::parse_duration)]
+        pub max_duration: chrono::Duration,
+
+        /// Command run through `sh -c` once the credential is issued, with the
+        /// grant in CORALGATE_* variables
+        #[arg(long, env = "CORALGATE_BREAK_GLASS_NOTIFY")]
+        pub notify: Option<String>,
+
+        /// Do not test the issued kubeconfig against the cluster
+        #[arg(long)]
+        pub skip_verify: bool,
+
+        /// Cluster CA bundle to embed in the kubeconfig, found from the
+        /// kubeconfig or the cluster when missing
+        #[arg(long)]
+        pub ca_file: Option<String>,
     }
 }
@@ -0,0 +1,81 @@
+use crate::{
+    command::structure::TeardownArgs,
+    core::{client::ClientManager, inventory, ledger, profile},
+    error::*,
+    output::{self, say},
+    shared,
+};
+
+/// Removes what `setup` and `generate` left in the cluster
+pub async fn handle(arguments: TeardownArgs) -> Result<()> {
+    // Profiles applied from YAML are only known by their label
+    let profile = match arguments.profile.as_deref() {
+        Some(name) => match profile::resolve(name, arguments.namespace.as_deref()) {
+            Ok(profile) => Some(profile.name().to_string()),
+            Err(CoralGateError::UnknownProfile(_)) => Some(name.to_string()),
+            Err(error) => return Err(error),
+        },
+        None => None,
+    };
+
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let mut objects = inventory::collect(&client).await?;
+    if let Some(profile) = &profile {
+        objects.retain(|object| {
+            let in_namespace = match (&object.namespace, &arguments.namespace) {
+                (Some(namespace), Some(wanted)) => namespace == wanted,
+                _ => true,
+            };
+
+            object.profile_name().as_ref() == Some(profile) && in_namespace
+        });
+    }
+
+    let (objects, kept) = if arguments.keep_active_grants {
+        let granted = ledger::list(&client, None)
+            .await?
+            .into_iter()
+            .filter(|grant| grant.is_active())
+            .map(|grant| grant.profile)
+            .collect();
+        inventory::retain_inactive(objects, &granted)
+    } else {
+        (objects, vec![])
+    };
+
+    for object in &kept {
+        say!("keeping  {} (active grant)", object);
+    }
+
+    let kept: Vec<String> = kept.iter().map(|object| object.to_string()).collect();
+    let removed: Vec<String> = objects.iter().map(|object| object.to_string()).collect();
+
+    if objects.is_empty() {
+        say!("Nothing to remove");
+        output::result(&serde_json::json!({ "removed": removed, "kept": kept }));
+        return Ok(());
+    }
+
+    for object in &objects {
+        say!("removing {}", object);
+    }
+
+    if !arguments.yes && !shared::confirm(&format!("Delete {} objects?", objects.len())).await? {
+        say!("Aborted");
+        output::result(&serde_json::json!({ "removed": [], "kept": kept, "aborted": true }));
+        return Ok(());
+    }
+
+    for object in &objects {
+        inventory::delete(&client, object).await?;
+    }
+
+    say!("Removed {} objects", objects.len());
+    output::result(&serde_json::json!({ "removed": removed, "kept": kept }));
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use crate::{
+    command::structure::UnrevokeArgs,
+    core::{client::ClientManager, revocation, rollback},
+    error::*,
+    output::{self, say},
+};
+
+/// Takes the user off the denylist, bindings removed by `revoke` are not restored
+pub async fn handle(arguments: UnrevokeArgs) -> Result<()> {
+    let mut client_manager = ClientManager::default();
+    let client = client_manager
+        .generate_kube_client(&arguments.kubeconfig)
+        .await?;
+
+    let (removed, pruned) = revocation::unrevoke(&client, &arguments.user).await?;
+    rollback::commit();
+
+    if removed {
+        say!("{} is no longer revoked", arguments.user);
+    } else {
+        say!("{} was not revoked", arguments.user);
+    }
+
+    for user in &pruned {
+        say!("pruned {}, its certificates expired", user);
+    }
+
+    output::result(&serde_json::json!({
+        "user": arguments.user,
+        "unrevoked": removed,
+        "prunedRevocations": pruned,
+    }));
+
+    Ok(())
+}
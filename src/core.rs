@@ -1,3 +1,27 @@
+pub mod access;
+pub mod activity;
+pub mod audit;
+pub mod ca;
+pub mod certificate;
 pub mod client;
 pub mod csr;
+pub mod doctor;
+pub mod encrypt;
+pub mod event;
+pub mod hook;
+pub mod ical;
+pub mod inventory;
+pub mod issue;
+pub mod ledger;
+pub mod lint;
+pub mod local_ca;
 pub mod profile;
+pub mod rbac;
+pub mod report;
+pub mod revocation;
+pub mod rollback;
+pub mod roster;
+pub mod schedule;
+pub mod suite;
+pub mod synthesize;
+pub mod verify;
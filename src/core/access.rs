@@ -0,0 +1,157 @@
+use crate::error::*;
+
+use std::fmt;
+
+use k8s_openapi::api::authorization::v1::{
+    NonResourceAttributes, ResourceAttributes, SelfSubjectAccessReview,
+    SelfSubjectAccessReviewSpec, SubjectAccessReview, SubjectAccessReviewSpec,
+};
+use kube::api::PostParams;
+use kube::{Api, Client};
+use serde::{Deserialize, Serialize};
+
+/// A single request a profile must or must not be allowed to make
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessCheck {
+    pub verb: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subresource: Option<String>,
+    /// A single object, like a signer or role name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Cluster wide when empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub non_resource_url: Option<String>,
+    pub allowed: bool,
+}
+
+impl AccessCheck {
+    pub fn allow(verb: &str, resource: &str) -> Self {
+        Self {
+            verb: verb.into(),
+            resource: Some(resource.into()),
+            allowed: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn deny(verb: &str, resource: &str) -> Self {
+        Self {
+            allowed: false,
+            ..Self::allow(verb, resource)
+        }
+    }
+
+    pub fn in_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    pub fn in_api_group(mut self, api_group: &str) -> Self {
+        self.api_group = Some(api_group.into());
+        self
+    }
+
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn resource_attributes(&self) -> Option<ResourceAttributes> {
+        if self.non_resource_url.is_some() {
+            return None;
+        }
+
+        Some(ResourceAttributes {
+            verb: Some(self.verb.clone()),
+            group: self.api_group.clone(),
+            resource: self.resource.clone(),
+            subresource: self.subresource.clone(),
+            name: self.name.clone(),
+            namespace: self.namespace.clone(),
+            ..Default::default()
+        })
+    }
+
+    pub fn non_resource_attributes(&self) -> Option<NonResourceAttributes> {
+        self.non_resource_url
+            .as_ref()
+            .map(|path| NonResourceAttributes {
+                path: Some(path.clone()),
+                verb: Some(self.verb.clone()),
+            })
+    }
+}
+
+impl fmt::Display for AccessCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expectation = if self.allowed { "can" } else { "can not" };
+
+        if let Some(path) = &self.non_resource_url {
+            return write!(f, "{} {} {}", expectation, self.verb, path);
+        }
+
+        let mut resource = self.resource.clone().unwrap_or("*".into());
+        if let Some(subresource) = &self.subresource {
+            resource = format!("{}/{}", resource, subresource);
+        }
+        if let Some(api_group) = self.api_group.as_ref().filter(|group| !group.is_empty()) {
+            resource = format!("{}.{}", resource, api_group);
+        }
+        if let Some(name) = &self.name {
+            resource = format!("{} {:?}", resource, name);
+        }
+
+        match &self.namespace {
+            Some(namespace) => write!(
+                f,
+                "{} {} {} in {}",
+                expectation, self.verb, resource, namespace
+            ),
+            None => write!(f, "{} {} {} cluster wide", expectation, self.verb, resource),
+        }
+    }
+}
+
+/// Asks the api server whether the client's own identity may do what the check describes
+pub async fn self_review(client: &Client, check: &AccessCheck) -> Result<bool> {
+    let api: Api<SelfSubjectAccessReview> = Api::all(client.clone());
+    let review = SelfSubjectAccessReview {
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: check.resource_attributes(),
+            non_resource_attributes: check.non_resource_attributes(),
+        },
+        ..Default::default()
+    };
+
+    let review = api.create(&PostParams::default(), &review).await?;
+
+    Ok(review.status.is_some_and(|status| status.allowed))
+}
+
+/// Asks the api server whether an authenticated member of the group may do what
+/// the check describes, the caller needs to be allowed to create subjectaccessreviews
+pub async fn group_review(client: &Client, group: &str, check: &AccessCheck) -> Result<bool> {
+    let api: Api<SubjectAccessReview> = Api::all(client.clone());
+    let review = SubjectAccessReview {
+        spec: SubjectAccessReviewSpec {
+            user: Some(format!("coralgate:review:{}", group)),
+            groups: Some(vec![group.into(), "system:authenticated".into()]),
+            resource_attributes: check.resource_attributes(),
+            non_resource_attributes: check.non_resource_attributes(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let review = api.create(&PostParams::default(), &review).await?;
+
+    Ok(review.status.is_some_and(|status| status.allowed))
+}
@@ -0,0 +1,184 @@
+use crate::core::audit::AuditEvent;
+use crate::core::ledger::Grant;
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// One request worth a look
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Action {
+    pub timestamp: Option<DateTime<Utc>>,
+    pub verb: String,
+    /// `resource/subresource`, the path for non resource requests
+    pub resource: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub code: Option<u16>,
+    pub audit_id: String,
+}
+
+impl From<&AuditEvent> for Action {
+    fn from(event: &AuditEvent) -> Self {
+        let resource = match event.resource() {
+            resource if resource.is_empty() => event.path().to_string(),
+            resource => resource,
+        };
+
+        Action {
+            timestamp: event.timestamp(),
+            verb: event.verb.clone(),
+            resource,
+            namespace: event
+                .object_ref
+                .as_ref()
+                .and_then(|object| object.namespace.clone()),
+            name: event
+                .object_ref
+                .as_ref()
+                .and_then(|object| object.name.clone()),
+            code: event.response_status.as_ref().map(|status| status.code),
+            audit_id: event.audit_id.clone(),
+        }
+    }
+}
+
+/// What one user did with the credentials coralgate issued
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserActivity {
+    pub user: String,
+    /// Serials of the certificates the requests were made with
+    pub serials: Vec<String>,
+    pub profiles: Vec<String>,
+    pub requests: usize,
+    pub mutating: Vec<Action>,
+    pub secret_reads: Vec<Action>,
+    pub exec_sessions: Vec<Action>,
+    pub denied: Vec<Action>,
+}
+
+/// The grant the request authenticated with: same CN, all its groups and
+/// made while the certificate was valid
+fn issued_by<'a>(event: &AuditEvent, grants: &'a [Grant]) -> Option<&'a Grant> {
+    let timestamp = event.timestamp()?;
+
+    grants.iter().find(|grant| {
+        grant.user == event.user.username
+            && grant
+                .groups
+                .iter()
+                .all(|group| event.user.groups.contains(group))
+            && timestamp >= grant.issued_at
+            && timestamp <= grant.expires_at
+    })
+}
+
+fn is_secret_read(event: &AuditEvent) -> bool {
+    event.resource() == "secrets" && matches!(event.verb.as_str(), "get" | "list" | "watch")
+}
+
+fn is_exec(event: &AuditEvent) -> bool {
+    matches!(
+        event.resource().as_str(),
+        "pods/exec" | "pods/attach" | "nodes/proxy"
+    )
+}
+
+/// Sums up the requests made with coralgate issued identities, per user
+pub fn summarize(events: &[AuditEvent], grants: &[Grant]) -> Vec<UserActivity> {
+    let mut activities: BTreeMap<&str, UserActivity> = BTreeMap::new();
+
+    for event in events {
+        let Some(grant) = issued_by(event, grants) else {
+            continue;
+        };
+
+        let activity = activities
+            .entry(&grant.user)
+            .or_insert_with(|| UserActivity {
+                user: grant.user.clone(),
+                ..Default::default()
+            });
+
+        if !activity.serials.contains(&grant.serial) {
+            activity.serials.push(grant.serial.clone());
+        }
+        if !activity.profiles.contains(&grant.profile) {
+            activity.profiles.push(grant.profile.clone());
+        }
+
+        activity.requests += 1;
+
+        if !event.is_allowed() {
+            activity.denied.push(event.into());
+        } else if is_exec(event) {
+            activity.exec_sessions.push(event.into());
+        } else if is_secret_read(event) {
+            activity.secret_reads.push(event.into());
+        } else if event.is_mutating() {
+            activity.mutating.push(event.into());
+        }
+    }
+
+    activities.into_values().collect()
+}
+
+fn render_actions(title: &str, actions: &[Action], report: &mut String) {
+    if actions.is_empty() {
+        return;
+    }
+
+    report.push_str(&format!("  {} ({}):\n", title, actions.len()));
+    for action in actions {
+        let target = match (&action.namespace, &action.name) {
+            (Some(namespace), Some(name)) => format!(" {}/{}", namespace, name),
+            (Some(namespace), None) => format!(" in {}", namespace),
+            (None, Some(name)) => format!(" {}", name),
+            (None, None) => String::new(),
+        };
+
+        report.push_str(&format!(
+            "    {} {} {}{}\n",
+            action
+                .timestamp
+                .map(|timestamp| timestamp.to_rfc3339())
+                .unwrap_or_default(),
+            action.verb,
+            action.resource,
+            target
+        ));
+    }
+}
+
+pub fn render_text(activities: &[UserActivity]) -> String {
+    let mut report = String::new();
+
+    if activities.is_empty() {
+        report.push_str("no requests made with coralgate issued identities\n");
+    }
+
+    for activity in activities {
+        report.push_str(&format!(
+            "{} ({}): {} requests\n",
+            activity.user,
+            activity.profiles.join(", "),
+            activity.requests
+        ));
+
+        render_actions("mutating", &activity.mutating, &mut report);
+        render_actions("secret reads", &activity.secret_reads, &mut report);
+        render_actions("exec sessions", &activity.exec_sessions, &mut report);
+        render_actions("denied", &activity.denied, &mut report);
+    }
+
+    report
+}
+
+pub fn render_json(activities: &[UserActivity]) -> String {
+    serde_json::to_string_pretty(activities).unwrap_or_default()
+}
@@ -0,0 +1,152 @@
+use crate::error::*;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/// The parts of an `audit.k8s.io/v1` Event coralgate looks at
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditEvent {
+    pub audit_id: String,
+    pub stage: String,
+    #[serde(rename = "requestURI")]
+    pub request_uri: String,
+    pub verb: String,
+    pub user: AuditUser,
+    pub impersonated_user: Option<AuditUser>,
+    #[serde(rename = "sourceIPs")]
+    pub source_ips: Vec<String>,
+    pub user_agent: Option<String>,
+    pub object_ref: Option<ObjectReference>,
+    pub response_status: Option<ResponseStatus>,
+    pub request_received_timestamp: Option<DateTime<Utc>>,
+    pub stage_timestamp: Option<DateTime<Utc>>,
+    pub annotations: std::collections::BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditUser {
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ObjectReference {
+    pub resource: String,
+    pub namespace: Option<String>,
+    pub name: Option<String>,
+    pub api_group: Option<String>,
+    pub subresource: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ResponseStatus {
+    pub code: u16,
+}
+
+impl AuditEvent {
+    /// The identity the request acted as, impersonation included
+    pub fn effective_user(&self) -> &AuditUser {
+        self.impersonated_user.as_ref().unwrap_or(&self.user)
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.request_received_timestamp.or(self.stage_timestamp)
+    }
+
+    /// Whether the authorizer let the request through
+    pub fn is_allowed(&self) -> bool {
+        match self.annotations.get("authorization.k8s.io/decision") {
+            Some(decision) => decision == "allow",
+            None => self
+                .response_status
+                .as_ref()
+                .is_none_or(|status| status.code != 401 && status.code != 403),
+        }
+    }
+
+    pub fn is_mutating(&self) -> bool {
+        matches!(
+            self.verb.as_str(),
+            "create" | "update" | "patch" | "delete" | "deletecollection"
+        )
+    }
+
+    /// `resource/subresource`, empty for non resource requests
+    pub fn resource(&self) -> String {
+        match &self.object_ref {
+            Some(object) => match &object.subresource {
+                Some(subresource) => format!("{}/{}", object.resource, subresource),
+                None => object.resource.clone(),
+            },
+            None => String::new(),
+        }
+    }
+
+    /// Request path without the query, used for non resource requests
+    pub fn path(&self) -> &str {
+        self.request_uri
+            .split('?')
+            .next()
+            .unwrap_or(&self.request_uri)
+    }
+}
+
+/// Which events of the log to keep
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub user: Option<String>,
+    pub group: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        let user = event.effective_user();
+
+        let identity = self.user.as_ref().is_none_or(|name| &user.username == name)
+            && self
+                .group
+                .as_ref()
+                .is_none_or(|group| user.groups.contains(group));
+
+        let window = match event.timestamp() {
+            Some(timestamp) => {
+                self.since.is_none_or(|since| timestamp >= since)
+                    && self.until.is_none_or(|until| timestamp <= until)
+            }
+            None => self.since.is_none() && self.until.is_none(),
+        };
+
+        identity && window
+    }
+}
+
+/// Reads a JSON lines audit log, keeping completed requests that pass the filter.
+/// Lines that are not audit events are skipped
+pub async fn read_events(path: &str, filter: &AuditFilter) -> Result<Vec<AuditEvent>> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut lines = BufReader::new(file).lines();
+    let mut events = Vec::new();
+
+    while let Some(line) = lines.next_line().await? {
+        let Ok(event) = serde_json::from_str::<AuditEvent>(&line) else {
+            continue;
+        };
+
+        // Each request is logged once per stage, only the last one counts
+        let completed =
+            event.stage.is_empty() || event.stage == "ResponseComplete" || event.stage == "Panic";
+
+        if completed && filter.matches(&event) {
+            events.push(event);
+        }
+    }
+
+    Ok(events)
+}
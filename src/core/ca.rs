@@ -0,0 +1,456 @@
+use crate::core::certificate;
+use crate::core::client::ClientManager;
+use crate::error::*;
+use crate::shared;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{Engine as _, engine::general_purpose};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::config::Kubeconfig;
+use kube::{Api, Client};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tracing::{debug, warn};
+
+/// Published in every namespace by the root CA publisher
+const ROOT_CA_CONFIG_MAP: &str = "kube-root-ca.crt";
+const ROOT_CA_KEY: &str = "ca.crt";
+
+/// Published for bootstrapping nodes, holds a kubeconfig with the CA
+const CLUSTER_INFO_NAMESPACE: &str = "kube-public";
+const CLUSTER_INFO_CONFIG_MAP: &str = "cluster-info";
+const CLUSTER_INFO_KEY: &str = "kubeconfig";
+
+const PRESENTED_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Settles the CA bundle embedded in issued kubeconfigs: `ca_file` when given,
+/// the kubeconfig's own CA data, the CA file it references, then what the
+/// cluster publishes in ConfigMaps. Warns when the api server presents a
+/// certificate the bundle does not trust
+pub async fn resolve(
+    client_manager: &mut ClientManager,
+    client: &Client,
+    kubeconfig_path: Option<&str>,
+    ca_file: Option<&str>,
+) -> Result<()> {
+    let (roots, source) = match ca_file {
+        Some(path) => (read_bundle(path).await?, path.to_string()),
+        None => match client_manager.get_root_cert() {
+            Ok(roots) => (roots.clone(), "the kubeconfig".to_string()),
+            Err(_) => discover(client, kubeconfig_path).await?,
+        },
+    };
+    debug!(source, certificates = roots.len(), "using CA bundle");
+
+    check_presented(&client_manager.cluster_url()?, &roots, &source).await;
+    client_manager.set_root_cert(roots);
+
+    Ok(())
+}
+
+/// Warns when the api server presents a certificate the bundle does not trust
+pub async fn check_presented(cluster_url: &str, roots: &[Vec<u8>], source: &str) {
+    match presented_chain(cluster_url).await {
+        Ok(presented) if !trusts(roots, &presented) => warn!(
+            source,
+            server = cluster_url,
+            "the CA bundle does not match the certificate the API server presents, issued kubeconfigs will fail TLS verification, pass --ca-file"
+        ),
+        Ok(_) => {}
+        Err(error) => debug!(%error, "could not compare the CA bundle with the API server"),
+    }
+}
+
+async fn discover(
+    client: &Client,
+    kubeconfig_path: Option<&str>,
+) -> Result<(Vec<Vec<u8>>, String)> {
+    if let Some(path) = kubeconfig_path {
+        match referenced_file(path).await {
+            Ok(Some(found)) => return Ok(found),
+            Ok(None) => {}
+            Err(error) => debug!(%error, "could not read the CA file the kubeconfig references"),
+        }
+    }
+
+    published(client)
+        .await
+        .ok_or(CoralGateError::ClientManagerRootCaMissing)
+}
+
+/// The bundle the cluster publishes, in `kube-root-ca.crt` or `cluster-info`.
+/// During a CA rotation it holds the old and the new CA
+pub async fn published(client: &Client) -> Option<(Vec<Vec<u8>>, String)> {
+    let api: Api<ConfigMap> = Api::default_namespaced(client.clone());
+    match api.get_opt(ROOT_CA_CONFIG_MAP).await {
+        Ok(Some(config_map)) => {
+            if let Some(pem) = config_map
+                .data
+                .as_ref()
+                .and_then(|data| data.get(ROOT_CA_KEY))
+                && let Ok(roots) = certificate::pem_to_der(pem.as_bytes())
+                && !roots.is_empty()
+            {
+                return Some((roots, format!("ConfigMap {}", ROOT_CA_CONFIG_MAP)));
+            }
+        }
+        Ok(None) => {}
+        Err(error) => debug!(%error, "could not read {}", ROOT_CA_CONFIG_MAP),
+    }
+
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), CLUSTER_INFO_NAMESPACE);
+    match api.get_opt(CLUSTER_INFO_CONFIG_MAP).await {
+        Ok(Some(config_map)) => {
+            if let Some(roots) = config_map
+                .data
+                .as_ref()
+                .and_then(|data| data.get(CLUSTER_INFO_KEY))
+                .and_then(|kubeconfig| cluster_info_roots(kubeconfig))
+            {
+                return Some((
+                    roots,
+                    format!(
+                        "ConfigMap {}/{}",
+                        CLUSTER_INFO_NAMESPACE, CLUSTER_INFO_CONFIG_MAP
+                    ),
+                ));
+            }
+        }
+        Ok(None) => {}
+        Err(error) => debug!(%error, "could not read {}", CLUSTER_INFO_CONFIG_MAP),
+    }
+
+    None
+}
+
+/// The `certificate-authority` file of the current context's cluster, relative
+/// paths are taken from the kubeconfig's directory
+async fn referenced_file(kubeconfig_path: &str) -> Result<Option<(Vec<Vec<u8>>, String)>> {
+    let kubeconfig_path = shared::resolve_path(kubeconfig_path)?;
+    let kubeconfig = Kubeconfig::read_from(&kubeconfig_path)?;
+
+    let Some(context) = kubeconfig
+        .contexts
+        .iter()
+        .find(|context| Some(&context.name) == kubeconfig.current_context.as_ref())
+        .and_then(|context| context.context.as_ref())
+    else {
+        return Ok(None);
+    };
+    let Some(ca_path) = kubeconfig
+        .clusters
+        .iter()
+        .find(|cluster| cluster.name == context.cluster)
+        .and_then(|cluster| cluster.cluster.as_ref())
+        .and_then(|cluster| cluster.certificate_authority.as_ref())
+    else {
+        return Ok(None);
+    };
+
+    let ca_path = match kubeconfig_path.parent() {
+        Some(directory) if Path::new(ca_path).is_relative() => directory.join(ca_path),
+        _ => ca_path.into(),
+    };
+    let source = ca_path.display().to_string();
+
+    Ok(Some((read_bundle(&source).await?, source)))
+}
+
+fn cluster_info_roots(kubeconfig: &str) -> Option<Vec<Vec<u8>>> {
+    let kubeconfig = Kubeconfig::from_yaml(kubeconfig).ok()?;
+    let data = kubeconfig
+        .clusters
+        .first()?
+        .cluster
+        .as_ref()?
+        .certificate_authority_data
+        .as_ref()?;
+    let pem = general_purpose::STANDARD.decode(data).ok()?;
+
+    certificate::pem_to_der(&pem).ok()
+}
+
+pub async fn read_bundle(path: &str) -> Result<Vec<Vec<u8>>> {
+    let invalid = |reason: String| CoralGateError::InvalidCa {
+        path: path.into(),
+        reason,
+    };
+
+    let pem = tokio::fs::read(shared::resolve_path(path)?)
+        .await
+        .map_err(|error| invalid(error.to_string()))?;
+    let roots = certificate::pem_to_der(&pem)?;
+    if roots.is_empty() {
+        return Err(invalid("no certificate found".into()));
+    }
+
+    Ok(roots)
+}
+
+/// Sets `certificate-authority-data` of the clusters pointing at `server` to
+/// the base64 encoded bundle, dropping their `certificate-authority` file
+/// references. Other clusters of a merged kubeconfig are left alone. The YAML
+/// is written anew, comments and key order are not kept. None when nothing
+/// changes
+pub fn replace_bundle(
+    kubeconfig: &str,
+    server: &str,
+    bundle: &str,
+) -> std::result::Result<Option<String>, String> {
+    let mut document: serde_yaml::Value =
+        serde_yaml::from_str(kubeconfig).map_err(|error| error.to_string())?;
+
+    let clusters = document
+        .get_mut("clusters")
+        .and_then(|clusters| clusters.as_sequence_mut())
+        .ok_or("no clusters")?;
+
+    let server = server.trim_end_matches('/');
+    let matching: Vec<&mut serde_yaml::Mapping> = clusters
+        .iter_mut()
+        .filter_map(|cluster| cluster.get_mut("cluster"))
+        .filter_map(|cluster| cluster.as_mapping_mut())
+        .filter(|cluster| {
+            cluster
+                .get("server")
+                .and_then(|url| url.as_str())
+                .is_some_and(|url| url.trim_end_matches('/') == server)
+        })
+        .collect();
+    if matching.is_empty() {
+        return Err(format!("no cluster with server {}", server));
+    }
+
+    let mut changed = false;
+    for cluster in matching {
+        let current = cluster
+            .get("certificate-authority-data")
+            .and_then(|data| data.as_str());
+        if current != Some(bundle) {
+            cluster.insert(
+                "certificate-authority-data".into(),
+                serde_yaml::Value::String(bundle.into()),
+            );
+            changed = true;
+        }
+        if cluster.remove("certificate-authority").is_some() {
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+
+    serde_yaml::to_string(&document)
+        .map(Some)
+        .map_err(|error| error.to_string())
+}
+
+/// Whether one of the presented certificates is in the bundle or signed by it
+pub fn trusts(roots: &[Vec<u8>], presented: &[Vec<u8>]) -> bool {
+    presented.iter().any(|certificate| {
+        roots.contains(certificate) || certificate::verify_chain(certificate, &[], roots).is_ok()
+    })
+}
+
+/// Certificates the api server presents in the TLS handshake, fetched without
+/// verifying them
+pub async fn presented_chain(cluster_url: &str) -> Result<Vec<Vec<u8>>> {
+    let failed = |reason: String| CoralGateError::CertificateChainError(reason);
+
+    let (host, port) = host_port(cluster_url)
+        .ok_or_else(|| failed(format!("can not parse server URL {}", cluster_url)))?;
+    let server_name = ServerName::try_from(host.clone())
+        .map_err(|error| failed(format!("invalid server name {}: {}", host, error)))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|error| failed(error.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAny(provider)))
+        .with_no_client_auth();
+
+    let handshake = async {
+        let stream = TcpStream::connect((host.as_str(), port)).await?;
+        TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+    };
+    let stream = tokio::time::timeout(PRESENTED_TIMEOUT, handshake)
+        .await
+        .map_err(|_| CoralGateError::TimeoutError(format!("TLS handshake with {}", cluster_url)))?
+        .map_err(|error| failed(error.to_string()))?;
+
+    Ok(stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .map(|certificate| certificate.to_vec())
+        .collect())
+}
+
+/// `https://host:port/...` to host and port, 443 when the port is left out
+fn host_port(url: &str) -> Option<(String, u16)> {
+    let authority = url
+        .strip_prefix("https://")
+        .unwrap_or(url)
+        .split('/')
+        .next()?;
+
+    let (host, port) = match authority.strip_prefix('[') {
+        // [::1]:6443
+        Some(rest) => {
+            let (host, rest) = rest.split_once(']')?;
+            (host, rest.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => 443,
+    };
+
+    Some((host.to_string(), port))
+}
+
+/// Only used to read what the server presents, the result is compared with the
+/// bundle afterwards
+#[derive(Debug)]
+struct AcceptAny(Arc<rustls::crypto::CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAny {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            certificate,
+            signature,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            certificate,
+            signature,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KUBECONFIG: &str = "\
+apiVersion: v1
+kind: Config
+clusters:
+  - name: prod
+    cluster:
+      server: https://prod.example.com:6443/
+      certificate-authority: /etc/old-ca.crt
+  - name: staging
+    cluster:
+      server: https://staging.example.com:6443
+      certificate-authority-data: c3RhZ2luZw==
+";
+
+    fn cluster_field(kubeconfig: &str, name: &str, field: &str) -> Option<String> {
+        let document: serde_yaml::Value = serde_yaml::from_str(kubeconfig).unwrap();
+        document["clusters"]
+            .as_sequence()?
+            .iter()
+            .find(|cluster| cluster["name"].as_str() == Some(name))?["cluster"]
+            .get(field)?
+            .as_str()
+            .map(String::from)
+    }
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(
+            host_port("https://prod.example.com:6443"),
+            Some(("prod.example.com".into(), 6443))
+        );
+        assert_eq!(
+            host_port("https://prod.example.com/api"),
+            Some(("prod.example.com".into(), 443))
+        );
+        assert_eq!(host_port("https://[::1]:6443"), Some(("::1".into(), 6443)));
+        assert_eq!(
+            host_port("https://[fd00::1]"),
+            Some(("fd00::1".into(), 443))
+        );
+        assert_eq!(host_port("https://prod.example.com:https"), None);
+    }
+
+    #[test]
+    fn replaces_the_bundle_of_the_matching_cluster() {
+        let updated = replace_bundle(KUBECONFIG, "https://prod.example.com:6443", "bmV3")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            cluster_field(&updated, "prod", "certificate-authority-data").as_deref(),
+            Some("bmV3")
+        );
+        assert_eq!(
+            cluster_field(&updated, "prod", "certificate-authority"),
+            None
+        );
+        assert_eq!(
+            cluster_field(&updated, "staging", "certificate-authority-data").as_deref(),
+            Some("c3RhZ2luZw==")
+        );
+
+        assert_eq!(
+            replace_bundle(&updated, "https://prod.example.com:6443", "bmV3"),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn refuses_kubeconfigs_of_other_clusters() {
+        assert!(replace_bundle(KUBECONFIG, "https://other.example.com:6443", "bmV3").is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::error::*;
+
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
+use kube::config::Kubeconfig;
+use x509_parser::pem::Pem;
+use x509_parser::prelude::*;
+
+/// Splits a PEM bundle into DER encoded certificates
+pub fn pem_to_der(pem: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut certificates = Vec::new();
+
+    for block in Pem::iter_from_buffer(pem) {
+        let block =
+            block.map_err(|error| CoralGateError::CertificateParseError(error.to_string()))?;
+        certificates.push(block.contents);
+    }
+
+    if certificates.is_empty() {
+        return Err(CoralGateError::CertificateParseError(
+            "no certificate found in PEM data".into(),
+        ));
+    }
+
+    Ok(certificates)
+}
+
+pub fn parse(der: &[u8]) -> Result<X509Certificate<'_>> {
+    let (_, certificate) = X509Certificate::from_der(der)
+        .map_err(|error| CoralGateError::CertificateParseError(error.to_string()))?;
+
+    Ok(certificate)
+}
+
+/// Checks the certificate and its issuers are currently valid and that it
+/// chains to one of the roots, through the intermediates when needed
+pub fn verify_chain(der: &[u8], intermediates: &[Vec<u8>], roots: &[Vec<u8>]) -> Result<()> {
+    let roots = roots
+        .iter()
+        .map(|root| parse(root))
+        .collect::<Result<Vec<_>>>()?;
+    // Each intermediate is used once at most, so a loop in the bundle ends
+    let mut intermediates = intermediates
+        .iter()
+        .map(|intermediate| parse(intermediate))
+        .collect::<Result<Vec<_>>>()?;
+    let mut certificate = parse(der)?;
+
+    loop {
+        if !certificate.validity().is_valid() {
+            return Err(CoralGateError::CertificateChainError(format!(
+                "certificate for {} is not valid now (valid {} to {})",
+                certificate.subject(),
+                certificate.validity().not_before,
+                certificate.validity().not_after
+            )));
+        }
+
+        if roots.iter().any(|root| signed_by(&certificate, root)) {
+            return Ok(());
+        }
+
+        match intermediates
+            .iter()
+            .position(|intermediate| intermediate.is_ca() && signed_by(&certificate, intermediate))
+        {
+            Some(index) => certificate = intermediates.swap_remove(index),
+            None => {
+                return Err(CoralGateError::CertificateChainError(format!(
+                    "certificate issued by {} does not chain to the cluster CA",
+                    certificate.issuer()
+                )));
+            }
+        }
+    }
+}
+
+fn signed_by(certificate: &X509Certificate, issuer: &X509Certificate) -> bool {
+    issuer.subject() == certificate.issuer()
+        && certificate
+            .verify_signature(Some(issuer.public_key()))
+            .is_ok()
+}
+
+/// The parts of a client certificate kubernetes cares about
+#[derive(Debug, Clone)]
+pub struct CertificateSummary {
+    /// Common name, the kubernetes username
+    pub user: String,
+    /// Organizations, the kubernetes groups
+    pub groups: Vec<String>,
+    pub serial: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+
+pub fn summary(der: &[u8]) -> Result<CertificateSummary> {
+    let certificate = parse(der)?;
+    let subject = certificate.subject();
+
+    let user = subject
+        .iter_common_name()
+        .filter_map(|name| name.as_str().ok())
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let groups = subject
+        .iter_organization()
+        .filter_map(|group| group.as_str().ok())
+        .map(String::from)
+        .collect();
+
+    let validity = certificate.validity();
+
+    Ok(CertificateSummary {
+        user,
+        groups,
+        serial: certificate.raw_serial_as_string(),
+        not_before: to_datetime(validity.not_before),
+        not_after: to_datetime(validity.not_after),
+    })
+}
+
+/// Requested certificates api usages the certificate does not carry. Usages
+/// without a matching x509 extension bit (ipsec, sgc) are not checked
+pub fn missing_usages(der: &[u8], requested: &[String]) -> Result<Vec<String>> {
+    let certificate = parse(der)?;
+    let error = |error: X509Error| CoralGateError::CertificateParseError(error.to_string());
+
+    let key_usage = certificate.key_usage().map_err(error)?.map(|ext| ext.value);
+    let extended = certificate
+        .extended_key_usage()
+        .map_err(error)?
+        .map(|ext| ext.value);
+
+    let key = |check: fn(&KeyUsage) -> bool| key_usage.is_some_and(check);
+    let ext = |check: fn(&ExtendedKeyUsage) -> bool| extended.is_some_and(check);
+
+    Ok(requested
+        .iter()
+        .filter(|usage| {
+            let present = match usage.as_str() {
+                "signing" | "digital signature" => key(KeyUsage::digital_signature),
+                "content commitment" => key(KeyUsage::non_repudiation),
+                "key encipherment" => key(KeyUsage::key_encipherment),
+                "key agreement" => key(KeyUsage::key_agreement),
+                "data encipherment" => key(KeyUsage::data_encipherment),
+                "cert sign" => key(KeyUsage::key_cert_sign),
+                "crl sign" => key(KeyUsage::crl_sign),
+                "encipher only" => key(KeyUsage::encipher_only),
+                "decipher only" => key(KeyUsage::decipher_only),
+                "any" => ext(|usage| usage.any),
+                "server auth" => ext(|usage| usage.server_auth),
+                "client auth" => ext(|usage| usage.client_auth),
+                "code signing" => ext(|usage| usage.code_signing),
+                "email protection" | "s/mime" => ext(|usage| usage.email_protection),
+                "timestamping" => ext(|usage| usage.time_stamping),
+                "ocsp signing" => ext(|usage| usage.ocsp_signing),
+                _ => true,
+            };
+            !present
+        })
+        .cloned()
+        .collect())
+}
+
+fn to_datetime(time: ASN1Time) -> DateTime<Utc> {
+    DateTime::from_timestamp(time.timestamp(), 0).unwrap_or_default()
+}
+
+/// Identity behind the client certificate of the kubeconfig's current context
+pub async fn identity_from_kubeconfig(kubeconfig: &Kubeconfig) -> Result<CertificateSummary> {
+    summary(&client_certificate(kubeconfig).await?)
+}
+
+/// DER of the client certificate of the kubeconfig's current context
+pub async fn client_certificate(kubeconfig: &Kubeconfig) -> Result<Vec<u8>> {
+    let mut chain = client_certificate_chain(kubeconfig).await?;
+    Ok(chain.swap_remove(0))
+}
+
+/// DER of the client certificate of the kubeconfig's current context,
+/// followed by the intermediates bundled with it
+pub async fn client_certificate_chain(kubeconfig: &Kubeconfig) -> Result<Vec<Vec<u8>>> {
+    let missing = || CoralGateError::KubeconfigWithoutCertificate;
+
+    let user = kubeconfig
+        .current_context
+        .as_ref()
+        .and_then(|current| {
+            kubeconfig
+                .contexts
+                .iter()
+                .find(|context| &context.name == current)
+        })
+        .and_then(|context| context.context.as_ref())
+        .and_then(|context| context.user.clone())
+        .ok_or_else(missing)?;
+
+    let auth_info = kubeconfig
+        .auth_infos
+        .iter()
+        .find(|auth_info| auth_info.name == user)
+        .and_then(|auth_info| auth_info.auth_info.as_ref())
+        .ok_or_else(missing)?;
+
+    let pem = match (
+        &auth_info.client_certificate_data,
+        &auth_info.client_certificate,
+    ) {
+        (Some(data), _) => general_purpose::STANDARD
+            .decode(data.trim())
+            .map_err(|error| CoralGateError::CertificateParseError(error.to_string()))?,
+        (None, Some(path)) => tokio::fs::read(path).await?,
+        (None, None) => return Err(missing()),
+    };
+
+    pem_to_der(&pem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer,
+        KeyPair, KeyUsagePurpose,
+    };
+
+    fn usages(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn client_certificate(key_usages: Vec<KeyUsagePurpose>) -> Vec<u8> {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.key_usages = key_usages;
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+
+        let key = KeyPair::generate().unwrap();
+        params.self_signed(&key).unwrap().der().to_vec()
+    }
+
+    fn ca_params(name: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign];
+        params
+    }
+
+    #[test]
+    fn verifies_through_intermediates() {
+        let root_key = KeyPair::generate().unwrap();
+        let root_params = ca_params("coralgate-test-root");
+        let root = root_params.self_signed(&root_key).unwrap();
+        let root_issuer = Issuer::new(root_params, root_key);
+
+        let intermediate_key = KeyPair::generate().unwrap();
+        let intermediate_params = ca_params("coralgate-test-intermediate");
+        let intermediate = intermediate_params
+            .signed_by(&intermediate_key, &root_issuer)
+            .unwrap();
+        let intermediate_issuer = Issuer::new(intermediate_params, intermediate_key);
+
+        let mut leaf_params = CertificateParams::new(vec![]).unwrap();
+        leaf_params
+            .distinguished_name
+            .push(DnType::CommonName, "alice");
+        let leaf = leaf_params
+            .signed_by(&KeyPair::generate().unwrap(), &intermediate_issuer)
+            .unwrap();
+
+        let roots = [root.der().to_vec()];
+        let intermediates = [intermediate.der().to_vec()];
+
+        verify_chain(leaf.der(), &intermediates, &roots).unwrap();
+        assert!(verify_chain(leaf.der(), &[], &roots).is_err());
+        assert!(verify_chain(leaf.der(), &intermediates, &[]).is_err());
+    }
+
+    #[test]
+    fn finds_missing_usages() {
+        let der = client_certificate(vec![KeyUsagePurpose::DigitalSignature]);
+
+        let missing = missing_usages(
+            &der,
+            &usages(&[
+                "client auth",
+                "digital signature",
+                "key encipherment",
+                "server auth",
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(missing, usages(&["key encipherment", "server auth"]));
+    }
+
+    #[test]
+    fn skips_usages_without_an_extension_bit() {
+        let der = client_certificate(vec![]);
+
+        let missing =
+            missing_usages(&der, &usages(&["client auth", "ipsec tunnel", "sgc"])).unwrap();
+
+        assert!(missing.is_empty(), "{:?}", missing);
+    }
+
+    #[test]
+    fn key_usages_are_missing_without_the_extension() {
+        let der = client_certificate(vec![]);
+
+        let missing = missing_usages(&der, &usages(&["signing"])).unwrap();
+
+        assert_eq!(missing, usages(&["signing"]));
+    }
+}
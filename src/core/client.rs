@@ -1,8 +1,8 @@
-use crate::{Result, error::CoralGateError};
+use crate::{Result, error::CoralGateError, shared};
 
 use base64::{Engine as _, engine::general_purpose};
 use kube::config::KubeConfigOptions;
-use std::{env, path::PathBuf, sync::OnceLock};
+use std::sync::OnceLock;
 
 static KUBE_CLIENT: OnceLock<kube::Client> = OnceLock::new();
 static KUBE_CONFIG: OnceLock<kube::Config> = OnceLock::new();
@@ -11,11 +11,20 @@ static KUBE_CONFIG: OnceLock<kube::Config> = OnceLock::new();
 pub struct ClientManager {
     config: Option<kube::Config>,
     client: Option<kube::Client>,
+    /// CA bundle found by [`crate::core::ca::resolve`], preferred over the config's
+    root_cert: Option<Vec<Vec<u8>>>,
 }
 
 impl ClientManager {
-    pub async fn get_kube_client() -> &'static kube::Client {
-        KUBE_CLIENT.get().expect("Kube config is not set")
+    pub async fn get_kube_client() -> Result<&'static kube::Client> {
+        KUBE_CLIENT
+            .get()
+            .ok_or(CoralGateError::ClientManagerConfigNotInitialized)
+    }
+
+    /// The client of this run, none before a command created it
+    pub fn client() -> Option<&'static kube::Client> {
+        KUBE_CLIENT.get()
     }
 
     pub async fn generate_kube_client(
@@ -24,15 +33,15 @@ impl ClientManager {
     ) -> Result<kube::Client> {
         let client = match custom_config_path {
             Some(kube_config_path) => {
-                let path = self.resolve_kube_path(kube_config_path);
+                let path = shared::resolve_path(kube_config_path)?;
                 let kubeconfig = kube::config::Kubeconfig::read_from(path)?;
                 let config =
                     kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
                         .await?;
 
                 self.config = Some(config.clone());
-                let client = kube::Client::try_from(config)?;
-                client
+
+                kube::Client::try_from(config)?
             }
 
             None => {
@@ -40,40 +49,28 @@ impl ClientManager {
                 let config = kube::Config::from_kubeconfig(&kubeconfig_options).await?;
                 self.config = Some(config.clone());
 
-                kube::Client::try_default().await.unwrap()
+                kube::Client::try_from(config)?
             }
         };
 
-        KUBE_CLIENT
-            .set(client.clone())
-            .unwrap_or_else(|_| panic!("How you doing ? "));
+        // Rollback uses the first client of the run, later ones talk to the same cluster
+        let _ = KUBE_CLIENT.set(client.clone());
 
         Ok(client)
     }
 
-    fn resolve_kube_path(&self, input_path: &str) -> PathBuf {
-        if input_path.starts_with('~') {
-            let home = env::var("HOME")
-                .or_else(|_| env::var("USERPROFILE"))
-                .expect("Could not find home directory environment variable");
-
-            let mut path = PathBuf::from(home);
-            let stripped = input_path
-                .strip_prefix("~/")
-                .unwrap_or(input_path.strip_prefix("~").unwrap_or(""));
-            path.push(stripped);
-            path
-        } else {
-            PathBuf::from(input_path)
-        }
+    pub fn set_root_cert(&mut self, root_cert: Vec<Vec<u8>>) {
+        self.root_cert = Some(root_cert);
     }
 
     pub fn get_root_cert(&self) -> Result<&Vec<Vec<u8>>> {
-        if let Some(config) = &self.config {
+        if let Some(root_cert) = &self.root_cert {
+            Ok(root_cert)
+        } else if let Some(config) = &self.config {
             if let Some(root_cert) = config.root_cert.as_ref() {
-                return Ok(root_cert);
+                Ok(root_cert)
             } else {
-                return Err(CoralGateError::ClientManagerRootCaMissing);
+                Err(CoralGateError::ClientManagerRootCaMissing)
             }
         } else {
             Err(CoralGateError::ClientManagerConfigNotInitialized)
@@ -85,12 +82,12 @@ impl ClientManager {
         let mut pem_bundle = String::new();
 
         for der_cert in cert_chain {
-            let b64 = general_purpose::STANDARD.encode(&der_cert);
+            let b64 = general_purpose::STANDARD.encode(der_cert);
 
             pem_bundle.push_str("-----BEGIN CERTIFICATE-----\n");
 
             for chunk in b64.as_bytes().chunks(64) {
-                pem_bundle.push_str(std::str::from_utf8(chunk).unwrap());
+                pem_bundle.push_str(&String::from_utf8_lossy(chunk));
                 pem_bundle.push('\n');
             }
 
@@ -102,8 +99,8 @@ impl ClientManager {
 
     pub fn cluster_url(&self) -> Result<String> {
         match &self.config {
-            Some(config) => return Ok(config.cluster_url.to_string()),
-            None => return Err(CoralGateError::ClientManagerConfigNotInitialized),
+            Some(config) => Ok(config.cluster_url.to_string()),
+            None => Err(CoralGateError::ClientManagerConfigNotInitialized),
         }
     }
 }
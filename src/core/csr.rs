@@ -1,5 +1,5 @@
-use crate::{command::structure::GenerateArgs, error::*};
-use std::{env, path::PathBuf};
+use crate::{core::issue::IssueRequest, error::*, shared};
+use std::collections::BTreeMap;
 
 use k8s_openapi::{
     ByteString,
@@ -11,8 +11,115 @@ use k8s_openapi::{
 
 use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, PKCS_RSA_SHA256, RsaKeySize};
 use tokio::time::{self, sleep};
+use tracing::{debug, info, instrument};
+use x509_parser::prelude::{FromDer, X509CertificationRequest};
 
-const DEFAULT_RETRIE_COUNT: i32 = 32;
+/// How long to wait for the signer unless `--wait` says otherwise
+pub const DEFAULT_WAIT: &str = "1m";
+
+/// Between two looks at a pending CSR
+const POLL_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+/// Pending CSRs without a sign-by time are not going to be signed after this
+const STALE_AFTER_SECONDS: i64 = 600;
+
+pub const DEFAULT_SIGNER: &str = "kubernetes.io/kube-apiserver-client";
+pub const DEFAULT_USAGE: &str = "client auth";
+
+/// Key usages the certificates api accepts
+const KNOWN_USAGES: [&str; 23] = [
+    "signing",
+    "digital signature",
+    "content commitment",
+    "key encipherment",
+    "key agreement",
+    "data encipherment",
+    "cert sign",
+    "crl sign",
+    "encipher only",
+    "decipher only",
+    "any",
+    "server auth",
+    "client auth",
+    "code signing",
+    "email protection",
+    "s/mime",
+    "ipsec end system",
+    "ipsec tunnel",
+    "ipsec user",
+    "timestamping",
+    "ocsp signing",
+    "microsoft sgc",
+    "netscape sgc",
+];
+
+/// Usages the kube-apiserver-client signer allows
+const API_SERVER_CLIENT_USAGES: [&str; 3] =
+    ["client auth", "digital signature", "key encipherment"];
+
+/// Who signs the CSR and what the certificate may be used for
+#[derive(Debug, Clone)]
+pub struct SignerOptions {
+    pub name: String,
+    pub usages: Vec<String>,
+    /// Approve the CSR, off for signers whose own approver decides
+    pub approve: bool,
+    /// How long the signer, and an outside approver, get to issue the certificate
+    pub wait: chrono::Duration,
+}
+
+impl Default for SignerOptions {
+    fn default() -> Self {
+        SignerOptions {
+            name: DEFAULT_SIGNER.into(),
+            usages: vec![DEFAULT_USAGE.into()],
+            approve: true,
+            wait: chrono::Duration::minutes(1),
+        }
+    }
+}
+
+impl SignerOptions {
+    /// Rejects what the certificates api would reject, before anything is created
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| CoralGateError::InvalidSigner {
+            signer: self.name.clone(),
+            reason,
+        };
+
+        // Signer names are `<domain>/<path>`, like `example.com/internal`
+        match self.name.split_once('/') {
+            Some((domain, path)) if domain.contains('.') && !path.is_empty() => {}
+            _ => return Err(invalid("expected a name like example.com/signer".into())),
+        }
+
+        if self.usages.is_empty() {
+            return Err(invalid("at least one usage is required".into()));
+        }
+        if let Some(unknown) = self
+            .usages
+            .iter()
+            .find(|usage| !KNOWN_USAGES.contains(&usage.as_str()))
+        {
+            return Err(invalid(format!("unknown usage {:?}", unknown)));
+        }
+
+        if self.name == DEFAULT_SIGNER {
+            if !self.usages.iter().any(|usage| usage == DEFAULT_USAGE) {
+                return Err(invalid("client auth is required".into()));
+            }
+            if let Some(usage) = self
+                .usages
+                .iter()
+                .find(|usage| !API_SERVER_CLIENT_USAGES.contains(&usage.as_str()))
+            {
+                return Err(invalid(format!("usage {:?} is not allowed", usage)));
+            }
+        }
+
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub struct GeneratedCsrWithPem {
@@ -21,20 +128,27 @@ pub struct GeneratedCsrWithPem {
     pub key_pem: String,
 }
 
-pub async fn generate_certificate(user: &str, group: &str) -> Result<GeneratedCsrWithPem> {
-    let key_pair = KeyPair::generate_rsa_for(&PKCS_RSA_SHA256, RsaKeySize::_2048).unwrap();
+/// Without a group the certificate only carries the user, for per-user bindings
+#[instrument(name = "generate_key", skip_all)]
+pub async fn generate_certificate(user: &str, group: Option<&str>) -> Result<GeneratedCsrWithPem> {
+    let key_pair = KeyPair::generate_rsa_for(&PKCS_RSA_SHA256, RsaKeySize::_2048)
+        .map_err(|error| CoralGateError::KeyGenerationFailed(error.to_string()))?;
     let key_pem = key_pair.serialize_pem();
 
     let mut distinguished_name = DistinguishedName::new();
     let mut params = CertificateParams::default();
 
-    distinguished_name.push(DnType::OrganizationName, group);
+    if let Some(group) = group {
+        distinguished_name.push(DnType::OrganizationName, group);
+    }
     distinguished_name.push(DnType::CommonName, user);
 
     params.distinguished_name = distinguished_name;
     params.subject_alt_names = vec![];
 
-    let csr = params.serialize_request(&key_pair).unwrap();
+    let csr = params
+        .serialize_request(&key_pair)
+        .map_err(|error| CoralGateError::KeyGenerationFailed(error.to_string()))?;
 
     Ok(GeneratedCsrWithPem {
         csr,
@@ -48,24 +162,55 @@ pub async fn generate_certificate(user: &str, group: &str) -> Result<GeneratedCs
 * The certificates are automatically generated
 */
 pub fn generate_cert_sigining_request_object(
-    gen_args: &GenerateArgs,
+    issue_request: &IssueRequest,
     certificates: &GeneratedCsrWithPem,
 ) -> Result<K8SCertificateSigningRequest> {
-    let labels = crate::shared::generate_lables();
-    let pem_string = certificates.csr.pem().unwrap();
+    let mut labels = shared::generate_profile_lables(issue_request.profile.name());
+    let pem_string = certificates
+        .csr
+        .pem()
+        .map_err(|error| CoralGateError::KeyGenerationFailed(error.to_string()))?;
     let request = ByteString(pem_string.into_bytes());
 
+    let mut annotations = BTreeMap::from([
+        (
+            shared::DELETE_AFTER_ANNOTATION.to_string(),
+            shared::from_now(issue_request.csr_retention)?.to_rfc3339(),
+        ),
+        (
+            shared::SIGN_BY_ANNOTATION.to_string(),
+            shared::from_now(issue_request.signer.wait)?.to_rfc3339(),
+        ),
+    ]);
+    if let Some(reason) = &issue_request.reason {
+        annotations.insert(shared::REASON_ANNOTATION.to_string(), reason.clone());
+    }
+    if let Some(labels) = labels.as_mut() {
+        labels.insert(
+            shared::USER_LABEL.into(),
+            shared::unique_label(&issue_request.user),
+        );
+        if issue_request.break_glass {
+            labels.insert(shared::BREAK_GLASS_LABEL.into(), "true".into());
+        }
+    }
+
+    // The api server appends a random suffix, so a user can hold several
     let metadata = ObjectMeta {
-        name: Some(format!("{}-csr", gen_args.user)),
-        labels: labels,
+        generate_name: Some(format!(
+            "{}-csr-",
+            shared::sanitize_name(&issue_request.user)
+        )),
+        labels,
+        annotations: Some(annotations),
         ..Default::default()
     };
 
     let spec = CertificateSigningRequestSpec {
         request,
-        signer_name: "kubernetes.io/kube-apiserver-client".into(),
-        usages: Some(vec!["client auth".to_string()]),
-        expiration_seconds: Some(gen_args.expire * 3600),
+        signer_name: issue_request.signer.name.clone(),
+        usages: Some(issue_request.signer.usages.clone()),
+        expiration_seconds: Some(issue_request.expiration_seconds),
         ..Default::default()
     };
 
@@ -78,42 +223,29 @@ pub fn generate_cert_sigining_request_object(
     Ok(signing_request_object)
 }
 
-// TODO: Move to utils
-pub fn resolve_kube_path(input_path: &str) -> PathBuf {
-    if input_path.starts_with('~') {
-        let home = env::var("HOME")
-            .or_else(|_| env::var("USERPROFILE"))
-            .expect("Could not find home directory environment variable");
-
-        let mut path = PathBuf::from(home);
-        let stripped = input_path
-            .strip_prefix("~/")
-            .unwrap_or(input_path.strip_prefix("~").unwrap_or(""));
-
-        path.push(stripped);
-        path
-    } else {
-        PathBuf::from(input_path)
-    }
-}
-
+#[instrument(name = "create_csr", skip_all, fields(signer = %csr_object.spec.signer_name))]
 pub async fn create(
     csr_object: &K8SCertificateSigningRequest,
     api: &kube::Api<K8SCertificateSigningRequest>,
 ) -> Result<K8SCertificateSigningRequest> {
     let created_csr = api
-        .create(&kube::api::PostParams::default(), &csr_object)
-        .await
-        .unwrap();
+        .create(&kube::api::PostParams::default(), csr_object)
+        .await?;
+    info!(csr = created_csr.metadata.name.as_deref(), "created CSR");
 
     Ok(created_csr)
 }
 
+#[instrument(name = "approve_csr", skip_all, fields(csr = created_csr.metadata.name.as_deref()))]
 pub async fn approve(
     created_csr: &K8SCertificateSigningRequest,
     csr_api: &kube::Api<K8SCertificateSigningRequest>,
 ) -> Result<K8SCertificateSigningRequest> {
-    let csr_name = created_csr.metadata.name.as_ref().unwrap();
+    let csr_name = created_csr
+        .metadata
+        .name
+        .as_ref()
+        .ok_or_else(|| CoralGateError::MissingName("CertificateSigningRequest".into()))?;
     let approval_patch = serde_json::json!({
         "apiVersion": "certificates.k8s.io/v1",
         "kind": "CertificateSigningRequest",
@@ -134,30 +266,325 @@ pub async fn approve(
             &kube::api::PatchParams::default(),
             &kube::api::Patch::Merge(approval_patch),
         )
-        .await
-        .unwrap();
+        .await?;
+    info!("approved CSR");
 
     Ok(approved_csr)
 }
 
 /// Returns Byte String sined certificate
+#[instrument(name = "wait_for_certificate", skip(csr_api))]
 pub async fn get_signed_certificate(
-    name: &String,
+    name: &str,
     csr_api: &kube::Api<K8SCertificateSigningRequest>,
+    wait: chrono::Duration,
 ) -> Result<ByteString> {
-    for i in 0..DEFAULT_RETRIE_COUNT {
-        if let Ok(csr) = csr_api.get(name).await {
-            if let Some(status) = &csr.status {
-                if let Some(certificate) = &status.certificate {
-                    return Ok(certificate.clone());
+    let poll = async {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let csr = match csr_api.get(name).await {
+                Ok(csr) => csr,
+                Err(error) => {
+                    debug!(attempt, %error, "could not get CSR");
+                    sleep(POLL_INTERVAL).await;
+                    continue;
                 }
+            };
+
+            if let Some(status) = &csr.status
+                && let Some(certificate) = &status.certificate
+            {
+                info!(attempt, "certificate signed");
+                return Ok(certificate.clone());
+            }
+
+            // A denied or failed CSR never gets a certificate
+            if let Some(condition) = csr
+                .status
+                .as_ref()
+                .and_then(|status| status.conditions.as_ref())
+                .and_then(|conditions| {
+                    conditions.iter().find(|condition| {
+                        (condition.type_ == "Denied" || condition.type_ == "Failed")
+                            && condition.status == "True"
+                    })
+                })
+            {
+                return Err(CoralGateError::CsrRejected {
+                    name: name.into(),
+                    reason: format!(
+                        "{}: {}",
+                        condition.type_,
+                        condition.message.clone().unwrap_or_default()
+                    ),
+                });
             }
-            sleep(time::Duration::from_secs(1)).await;
+
+            debug!(attempt, "certificate not signed yet");
+            sleep(POLL_INTERVAL).await;
+        }
+    };
+
+    time::timeout(wait.to_std().unwrap_or_default(), poll)
+        .await
+        .map_err(|_| {
+            CoralGateError::TimeoutError(format!(
+                "CSR {} was not signed within {}s",
+                name,
+                wait.num_seconds()
+            ))
+        })?
+}
+
+/// Why `gc` would remove a coralgate CSR, if it would
+pub fn cleanup_reason(csr: &K8SCertificateSigningRequest) -> Option<&'static str> {
+    let now = chrono::Utc::now();
+    let status = csr.status.as_ref();
+    let issued = status.is_some_and(|status| status.certificate.is_some());
+
+    let rejected = status
+        .and_then(|status| status.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions.iter().any(|condition| {
+                (condition.type_ == "Denied" || condition.type_ == "Failed")
+                    && condition.status == "True"
+            })
+        });
+    if rejected {
+        return Some("rejected");
+    }
+
+    // Someone may still approve it while the issuing command waits
+    let annotation = |name: &str| {
+        csr.metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(name))
+            .and_then(|value| chrono::DateTime::parse_from_rfc3339(value).ok())
+    };
+    let sign_by = annotation(shared::SIGN_BY_ANNOTATION).or_else(|| {
+        csr.metadata
+            .creation_timestamp
+            .as_ref()
+            .and_then(|created| chrono::DateTime::from_timestamp(created.0.as_second(), 0))
+            .map(|created| (created + chrono::Duration::seconds(STALE_AFTER_SECONDS)).into())
+    });
+    if !issued && sign_by.is_some_and(|sign_by| sign_by < now) {
+        return Some("stale");
+    }
+
+    let delete_after = annotation(shared::DELETE_AFTER_ANNOTATION);
+    if issued && delete_after.is_some_and(|delete_after| delete_after < now) {
+        return Some("past retention");
+    }
+
+    None
+}
+
+/// Common name the CSR asks a certificate for, the exact user the label
+/// only holds a sanitized form of
+fn requested_user(csr: &K8SCertificateSigningRequest) -> Option<String> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&csr.spec.request.0).ok()?;
+    let (_, request) = X509CertificationRequest::from_der(&pem.contents).ok()?;
+
+    request
+        .certification_request_info
+        .subject
+        .iter_common_name()
+        .next()?
+        .as_str()
+        .ok()
+        .map(String::from)
+}
+
+/// Coralgate CSRs `gc` would remove, optionally only the ones of a user,
+/// with the reason
+pub async fn cleanup_candidates(
+    api: &kube::Api<K8SCertificateSigningRequest>,
+    user: Option<&str>,
+) -> Result<Vec<(String, &'static str)>> {
+    let mut selector = shared::label_selector(None);
+    if let Some(user) = user {
+        selector.push_str(&format!(",{}", shared::user_selector(user)));
+    }
+
+    let csrs = api
+        .list(&kube::api::ListParams::default().labels(&selector))
+        .await?;
+
+    Ok(csrs
+        .into_iter()
+        .filter(|csr| user.is_none_or(|user| requested_user(csr).as_deref() == Some(user)))
+        .filter_map(|csr| {
+            let reason = cleanup_reason(&csr)?;
+            Some((csr.metadata.name?, reason))
+        })
+        .collect())
+}
+
+/// Deletes a CSR, one that is already gone counts as deleted
+pub async fn delete(api: &kube::Api<K8SCertificateSigningRequest>, name: &str) -> Result<()> {
+    match api.delete(name, &kube::api::DeleteParams::default()).await {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(status)) if status.is_not_found() => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::api::certificates::v1::{
+        CertificateSigningRequestCondition, CertificateSigningRequestStatus,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::jiff::Timestamp;
+
+    fn signer(name: &str, usages: &[&str]) -> SignerOptions {
+        SignerOptions {
+            name: name.into(),
+            usages: usages.iter().map(|usage| usage.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn csr(
+        age_seconds: i64,
+        issued: bool,
+        condition: Option<&str>,
+        annotations: &[(&str, chrono::DateTime<chrono::Utc>)],
+    ) -> K8SCertificateSigningRequest {
+        let created = Timestamp::from_second(chrono::Utc::now().timestamp() - age_seconds).unwrap();
+
+        K8SCertificateSigningRequest {
+            metadata: ObjectMeta {
+                creation_timestamp: Some(Time(created)),
+                annotations: Some(
+                    annotations
+                        .iter()
+                        .map(|(name, time)| (name.to_string(), time.to_rfc3339()))
+                        .collect(),
+                ),
+                ..Default::default()
+            },
+            status: Some(CertificateSigningRequestStatus {
+                certificate: issued.then(|| ByteString(b"certificate".to_vec())),
+                conditions: condition.map(|type_| {
+                    vec![CertificateSigningRequestCondition {
+                        type_: type_.into(),
+                        status: "True".into(),
+                        ..Default::default()
+                    }]
+                }),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accepts_the_default_and_custom_signers() {
+        assert!(SignerOptions::default().validate().is_ok());
+        assert!(
+            signer(DEFAULT_SIGNER, &["client auth", "digital signature"])
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            signer("example.com/internal", &["server auth", "code signing"])
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn rejects_what_the_certificates_api_rejects() {
+        for signer in [
+            signer("internal", &["client auth"]),
+            signer("example/internal", &["client auth"]),
+            signer("example.com/", &["client auth"]),
+            signer("example.com/internal", &[]),
+            signer("example.com/internal", &["client-auth"]),
+            signer(DEFAULT_SIGNER, &["digital signature"]),
+            signer(DEFAULT_SIGNER, &["client auth", "server auth"]),
+        ] {
+            assert!(
+                matches!(signer.validate(), Err(CoralGateError::InvalidSigner { .. })),
+                "{:?}",
+                signer
+            );
         }
     }
 
-    sleep(time::Duration::from_secs(30)).await;
-    Err(CoralGateError::TimeoutError(
-        "Timed out getting signed certificate".into(),
-    ))
+    #[test]
+    fn removes_rejected_stale_and_retained_csrs() {
+        let past = chrono::Utc::now() - chrono::Duration::hours(1);
+
+        assert_eq!(
+            cleanup_reason(&csr(0, false, Some("Denied"), &[])),
+            Some("rejected")
+        );
+        assert_eq!(
+            cleanup_reason(&csr(0, true, Some("Failed"), &[])),
+            Some("rejected")
+        );
+        assert_eq!(cleanup_reason(&csr(3600, false, None, &[])), Some("stale"));
+        assert_eq!(
+            cleanup_reason(&csr(
+                7200,
+                true,
+                Some("Approved"),
+                &[(shared::DELETE_AFTER_ANNOTATION, past)]
+            )),
+            Some("past retention")
+        );
+    }
+
+    #[test]
+    fn keeps_pending_and_retained_csrs() {
+        let future = chrono::Utc::now() + chrono::Duration::hours(1);
+
+        assert_eq!(cleanup_reason(&csr(60, false, None, &[])), None);
+        assert_eq!(cleanup_reason(&csr(60, false, Some("Approved"), &[])), None);
+        assert_eq!(
+            cleanup_reason(&csr(
+                7200,
+                true,
+                None,
+                &[(shared::DELETE_AFTER_ANNOTATION, future)]
+            )),
+            None
+        );
+        assert_eq!(cleanup_reason(&csr(7200, true, None, &[])), None);
+    }
+
+    #[test]
+    fn waits_for_the_requested_time_before_calling_a_csr_stale() {
+        let now = chrono::Utc::now();
+        let sign_by = |minutes| {
+            [(
+                shared::SIGN_BY_ANNOTATION,
+                now + chrono::Duration::minutes(minutes),
+            )]
+        };
+
+        assert_eq!(cleanup_reason(&csr(3600, false, None, &sign_by(30))), None);
+        assert_eq!(
+            cleanup_reason(&csr(3600, false, Some("Approved"), &sign_by(-1))),
+            Some("stale")
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_the_exact_requested_user() {
+        let generated = generate_certificate("bob.smith", Some("dev"))
+            .await
+            .unwrap();
+        let mut request = csr(0, false, None, &[]);
+        request.spec.request = ByteString(generated.csr.pem().unwrap().into_bytes());
+
+        assert_eq!(requested_user(&request).as_deref(), Some("bob.smith"));
+        assert_eq!(requested_user(&csr(0, false, None, &[])), None);
+    }
 }
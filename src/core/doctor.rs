@@ -0,0 +1,254 @@
+use crate::core::access::{self, AccessCheck};
+use crate::core::profile::{Change, Profile};
+use crate::shared;
+
+use std::collections::BTreeSet;
+
+use kube::Client;
+use serde::Serialize;
+
+/// Oldest minor release whose signers honour `expirationSeconds` on CSRs
+const EXPIRATION_SECONDS_MINOR: u32 = 22;
+
+const CERTIFICATES_API: &str = "certificates.k8s.io/v1";
+const RBAC_GROUP: &str = "rbac.authorization.k8s.io";
+
+/// One line of the checklist
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: true,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed: false,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Checks what issuing with `signer` and the given profiles needs from the
+/// cluster and from the kubeconfig's identity. Stops after connectivity when
+/// the api server can not be reached
+pub async fn run(client: &Client, signer: &str, profiles: &[Profile]) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let version = match client.apiserver_version().await {
+        Ok(version) => version,
+        Err(error) => {
+            checks.push(Check::fail(
+                "connectivity",
+                error.to_string(),
+                "check the server URL and credentials in the kubeconfig",
+            ));
+            return checks;
+        }
+    };
+    checks.push(Check::pass(
+        "connectivity",
+        format!("API server {}", version.git_version),
+    ));
+    checks.push(expiration_seconds(&version.major, &version.minor));
+    checks.push(certificates_api(client).await);
+
+    for (check, hint) in permissions(signer, profiles) {
+        checks.push(permission(client, check, hint).await);
+    }
+
+    for profile in profiles {
+        checks.push(installed(client, profile).await);
+    }
+
+    checks
+}
+
+fn expiration_seconds(major: &str, minor: &str) -> Check {
+    let name = "expirationSeconds support";
+    let minor_number = minor.trim_end_matches('+').parse::<u32>();
+
+    match (major, minor_number) {
+        ("1", Ok(minor_number)) if minor_number >= EXPIRATION_SECONDS_MINOR => Check::pass(
+            name,
+            format!(
+                "Kubernetes {}.{} honours the requested validity",
+                major, minor
+            ),
+        ),
+        ("1", Ok(_)) => Check::fail(
+            name,
+            format!(
+                "Kubernetes {}.{} ignores expirationSeconds, certificates get the signer's default validity",
+                major, minor
+            ),
+            format!(
+                "upgrade to 1.{} or later, or sign locally with --ca-cert and --ca-key",
+                EXPIRATION_SECONDS_MINOR
+            ),
+        ),
+        _ => Check::fail(
+            name,
+            format!("unknown server version {}.{}", major, minor),
+            "check that the server is a Kubernetes API server",
+        ),
+    }
+}
+
+async fn certificates_api(client: &Client) -> Check {
+    let name = format!("{} api", CERTIFICATES_API);
+
+    match client.list_api_group_resources(CERTIFICATES_API).await {
+        Ok(resources)
+            if resources
+                .resources
+                .iter()
+                .any(|resource| resource.name == "certificatesigningrequests/approval") =>
+        {
+            Check::pass(name, "CSRs can be created and approved")
+        }
+        Ok(_) => Check::fail(
+            name,
+            "the api has no certificatesigningrequests/approval",
+            "sign locally with --ca-cert and --ca-key",
+        ),
+        Err(error) => Check::fail(
+            name,
+            error.to_string(),
+            "the certificates api is disabled, sign locally with --ca-cert and --ca-key",
+        ),
+    }
+}
+
+/// What the kubeconfig's identity needs, with what to do when it is missing
+fn permissions(signer: &str, profiles: &[Profile]) -> Vec<(AccessCheck, String)> {
+    let grant_hint = |check: &AccessCheck| {
+        format!(
+            "grant the kubeconfig's identity {}",
+            check.to_string().trim_start_matches("can ")
+        )
+    };
+
+    let mut checks = vec![
+        AccessCheck::allow("create", "certificatesigningrequests")
+            .in_api_group("certificates.k8s.io"),
+        AccessCheck {
+            subresource: Some("approval".into()),
+            ..AccessCheck::allow("update", "certificatesigningrequests")
+                .in_api_group("certificates.k8s.io")
+        },
+        AccessCheck::allow("create", "clusterrolebindings").in_api_group(RBAC_GROUP),
+        AccessCheck::allow("create", "configmaps").in_namespace(shared::NAMESPACE),
+    ];
+
+    let roles: BTreeSet<&str> = profiles
+        .iter()
+        .flat_map(|profile| &profile.resources)
+        .filter_map(|resource| resource.role_ref())
+        .filter(|role_ref| role_ref.kind == "ClusterRole")
+        .map(|role_ref| role_ref.name.as_str())
+        .collect();
+    for role in roles {
+        checks.push(
+            AccessCheck::allow("bind", "clusterroles")
+                .in_api_group(RBAC_GROUP)
+                .named(role),
+        );
+    }
+
+    let mut checks: Vec<(AccessCheck, String)> = checks
+        .into_iter()
+        .map(|check| {
+            let hint = grant_hint(&check);
+            (check, hint)
+        })
+        .collect();
+
+    let approve = AccessCheck::allow("approve", "signers")
+        .in_api_group("certificates.k8s.io")
+        .named(signer);
+    let hint = format!(
+        "{}, or pass --no-approve and let the signer's approver do it",
+        grant_hint(&approve)
+    );
+    checks.insert(2, (approve, hint));
+
+    checks
+}
+
+async fn permission(client: &Client, check: AccessCheck, hint: String) -> Check {
+    let name = check.to_string();
+
+    match access::self_review(client, &check).await {
+        Ok(true) => Check::pass(name, "allowed"),
+        Ok(false) => Check::fail(name, "denied", hint),
+        Err(error) => Check::fail(
+            name,
+            error.to_string(),
+            "the identity must be able to create selfsubjectaccessreviews",
+        ),
+    }
+}
+
+/// Whether `setup` has installed the profile as it is defined now
+async fn installed(client: &Client, profile: &Profile) -> Check {
+    let name = format!("profile {}", profile.name());
+
+    let diffs = match profile.diff(client).await {
+        Ok(diffs) => diffs,
+        Err(error) => return Check::fail(name, error.to_string(), "run coralgate setup"),
+    };
+
+    let missing: Vec<&str> = diffs
+        .iter()
+        .filter(|diff| matches!(diff.change, Change::Create))
+        .map(|diff| diff.resource.as_str())
+        .collect();
+    let outdated: Vec<&str> = diffs
+        .iter()
+        .filter(|diff| matches!(diff.change, Change::Update(_)))
+        .map(|diff| diff.resource.as_str())
+        .collect();
+    let foreign: Vec<&str> = diffs
+        .iter()
+        .filter(|diff| matches!(diff.change, Change::NotOwned))
+        .map(|diff| diff.resource.as_str())
+        .collect();
+
+    if !foreign.is_empty() {
+        Check::fail(
+            name,
+            format!("not owned by coralgate: {}", foreign.join(", ")),
+            "run coralgate setup --adopt",
+        )
+    } else if !missing.is_empty() {
+        Check::fail(
+            name,
+            format!("not installed: {}", missing.join(", ")),
+            "run coralgate setup",
+        )
+    } else if !outdated.is_empty() {
+        Check::fail(
+            name,
+            format!("out of date: {}", outdated.join(", ")),
+            "run coralgate setup",
+        )
+    } else {
+        Check::pass(name, "installed and up to date")
+    }
+}
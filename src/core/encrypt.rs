@@ -0,0 +1,36 @@
+use crate::error::*;
+
+use std::process::Stdio;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Encrypts for an age recipient (`age1...` or an ssh public key) with the
+/// `age` binary, the output is ASCII armored
+pub async fn age(recipient: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let failed = |reason: String| CoralGateError::EncryptionFailed {
+        recipient: recipient.into(),
+        reason,
+    };
+
+    let mut child = Command::new("age")
+        .args(["--armor", "--recipient", recipient])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| failed(format!("can not run age: {}", error)))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(plaintext).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
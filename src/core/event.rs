@@ -0,0 +1,44 @@
+use crate::core::ledger;
+use crate::error::*;
+use crate::shared;
+
+use k8s_openapi::api::core::v1::ObjectReference;
+use k8s_openapi::api::events::v1::Event;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta};
+use k8s_openapi::jiff::Timestamp;
+use kube::api::PostParams;
+use kube::{Api, Client};
+
+/// Records a Warning event in the coralgate namespace, visible with
+/// `kubectl get events -n coralgate`
+pub async fn warn(
+    client: &Client,
+    reason: &str,
+    note: &str,
+    regarding: Option<ObjectReference>,
+) -> Result<()> {
+    ledger::ensure_namespace(client).await?;
+
+    let event = Event {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}-", shared::sanitize_name(reason))),
+            namespace: Some(shared::NAMESPACE.into()),
+            labels: shared::generate_lables(),
+            ..Default::default()
+        },
+        event_time: Some(MicroTime(Timestamp::now())),
+        action: Some(reason.into()),
+        reason: Some(reason.into()),
+        note: Some(note.chars().take(1024).collect()),
+        type_: Some("Warning".into()),
+        regarding,
+        reporting_controller: Some(format!("{}/cli", shared::CREATED_BY_VALUE)),
+        reporting_instance: Some(std::env::var("USER").unwrap_or(shared::CREATED_BY_VALUE.into())),
+        ..Default::default()
+    };
+
+    let api: Api<Event> = Api::namespaced(client.clone(), shared::NAMESPACE);
+    api.create(&PostParams::default(), &event).await?;
+
+    Ok(())
+}
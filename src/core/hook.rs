@@ -0,0 +1,33 @@
+use crate::core::ledger::Grant;
+use crate::error::*;
+
+use tokio::process::Command;
+
+/// Runs a notification command through `sh -c`, the grant is passed in
+/// `CORALGATE_*` variables and as JSON in `CORALGATE_GRANT`
+pub async fn notify(command: &str, event: &str, grant: &Grant) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("CORALGATE_EVENT", event)
+        .env("CORALGATE_USER", &grant.user)
+        .env("CORALGATE_PROFILE", &grant.profile)
+        .env("CORALGATE_REASON", grant.reason.clone().unwrap_or_default())
+        .env("CORALGATE_EXPIRES_AT", grant.expires_at.to_rfc3339())
+        .env("CORALGATE_SERIAL", &grant.serial)
+        .env(
+            "CORALGATE_GRANT",
+            serde_json::to_string(grant).unwrap_or_default(),
+        )
+        .status()
+        .await?;
+
+    if !status.success() {
+        return Err(CoralGateError::HookFailed {
+            command: command.into(),
+            status: status.to_string(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,350 @@
+use crate::shared;
+
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rrule::RRuleSet;
+
+/// The parts of a VEVENT a shift needs
+#[derive(Debug, Clone, Default)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub summary: String,
+    /// Addresses of the ATTENDEE properties, without `mailto:`
+    pub attendees: Vec<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// DTSTART, RRULE, RDATE and EXDATE lines of a recurring event, see [`expand`]
+    pub recurrence: Vec<String>,
+    /// Set on an edited occurrence of a recurring event, which replaces the
+    /// occurrence starting at this time
+    pub recurrence_id: Option<DateTime<Utc>>,
+}
+
+impl CalendarEvent {
+    fn recurs(&self) -> bool {
+        self.recurrence
+            .iter()
+            .any(|line| !line.to_uppercase().starts_with("DTSTART"))
+    }
+}
+
+/// Joins folded lines, a line starting with a space or tab continues the previous one
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+/// A content line, `NAME;PARAM=x:VALUE`
+struct Property {
+    /// Upper case
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+fn split_property(line: &str) -> Option<Property> {
+    let (head, value) = line.split_once(':')?;
+    let mut parts = head.split(';');
+    let name = parts.next()?.to_uppercase();
+
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Date-times in UTC (`...Z`) are exact, ones with a TZID are read in that
+/// zone, floating ones in the local time zone. Dates start at midnight
+fn parse_time(value: &str, params: &[(String, String)]) -> Result<DateTime<Utc>, String> {
+    let invalid = || format!("invalid date-time {:?}", value);
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return Ok(Utc.from_utc_datetime(&naive));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(invalid)?;
+
+    let time = match params.iter().find(|(key, _)| key == "TZID") {
+        Some((_, tzid)) => {
+            let zone: Tz = tzid
+                .parse()
+                .map_err(|_| format!("unknown time zone {:?}", tzid))?;
+            zone.from_local_datetime(&naive)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+        }
+        None => Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc)),
+    };
+
+    time.ok_or_else(|| format!("{} does not exist in its time zone", value))
+}
+
+/// `P1D`, `PT8H30M` and the like, read by the command line parser once the
+/// designators are dropped
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let value = value.strip_prefix('P')?.replace('T', "");
+
+    shared::parse_duration(&value.to_lowercase()).ok()
+}
+
+/// Reads the VEVENTs of an iCalendar file, other components are ignored.
+/// Recurring events come back once, [`expand`] turns them into occurrences
+pub fn parse(content: &str) -> Result<Vec<CalendarEvent>, String> {
+    let mut events = Vec::new();
+    let mut current: Option<CalendarEvent> = None;
+    let mut duration: Option<Duration> = None;
+
+    for line in unfold(content) {
+        let Some(Property {
+            name,
+            params,
+            value,
+        }) = split_property(&line)
+        else {
+            continue;
+        };
+
+        match (name.as_str(), value.to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => {
+                current = Some(CalendarEvent::default());
+                duration = None;
+                continue;
+            }
+            ("END", "VEVENT") => {
+                if let Some(mut event) = current.take() {
+                    if event.end.is_none() {
+                        event.end = event
+                            .start
+                            .zip(duration)
+                            .and_then(|(start, duration)| start.checked_add_signed(duration));
+                    }
+                    events.push(event);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some(event) = current.as_mut() else {
+            continue;
+        };
+
+        let time = || parse_time(&value, &params).map_err(|error| format!("{}: {}", name, error));
+        match name.as_str() {
+            "UID" => event.uid = value.clone(),
+            "SUMMARY" => event.summary = value.replace("\\,", ",").trim().to_string(),
+            "DTSTART" => {
+                event.start = Some(time()?);
+                event.recurrence.insert(0, line.clone());
+            }
+            "DTEND" => event.end = Some(time()?),
+            "DURATION" => duration = parse_duration(&value),
+            "RECURRENCE-ID" => event.recurrence_id = Some(time()?),
+            "RRULE" | "RDATE" | "EXDATE" | "EXRULE" => event.recurrence.push(line.clone()),
+            "ATTENDEE" => {
+                let address = value
+                    .strip_prefix("mailto:")
+                    .or_else(|| value.strip_prefix("MAILTO:"))
+                    .unwrap_or(&value);
+
+                if !address.is_empty() {
+                    event.attendees.push(address.to_string());
+                } else if let Some((_, name)) = params.iter().find(|(key, _)| key == "CN") {
+                    event.attendees.push(name.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+/// Most occurrences one recurring event may have between `from` and `until`
+const MAX_OCCURRENCES: u16 = 10_000;
+
+/// Replaces recurring events by their occurrences overlapping `from` to
+/// `until`, each as long as the first one. Occurrences edited in their own
+/// event, with a RECURRENCE-ID, are left to that event
+pub fn expand(
+    events: Vec<CalendarEvent>,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<Vec<CalendarEvent>, String> {
+    let edited: BTreeSet<(String, DateTime<Utc>)> = events
+        .iter()
+        .filter_map(|event| Some((event.uid.clone(), event.recurrence_id?)))
+        .collect();
+
+    let mut expanded = Vec::new();
+    for event in events {
+        let (Some(start), Some(end)) = (event.start, event.end) else {
+            expanded.push(event);
+            continue;
+        };
+        if !event.recurs() {
+            expanded.push(event);
+            continue;
+        }
+
+        let invalid = |error: String| format!("recurrence of event {:?}: {}", event.uid, error);
+        let rules: RRuleSet = event
+            .recurrence
+            .join("\n")
+            .parse()
+            .map_err(|error: rrule::RRuleError| invalid(error.to_string()))?;
+
+        let length = end - start;
+        let occurrences = rules
+            .after((from - length).with_timezone(&rrule::Tz::UTC))
+            .before(until.with_timezone(&rrule::Tz::UTC))
+            .all(MAX_OCCURRENCES);
+        if occurrences.limited {
+            return Err(invalid(format!(
+                "more than {} occurrences, import a shorter --horizon",
+                MAX_OCCURRENCES
+            )));
+        }
+
+        for occurrence in occurrences.dates {
+            let start = occurrence.with_timezone(&Utc);
+            if edited.contains(&(event.uid.clone(), start)) {
+                continue;
+            }
+
+            expanded.push(CalendarEvent {
+                start: Some(start),
+                end: start.checked_add_signed(length),
+                recurrence: vec![],
+                ..event.clone()
+            });
+        }
+    }
+
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROTATION: &str = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:rotation\r
+SUMMARY:alice\r
+DTSTART;TZID=Europe/Berlin:20261005T090000\r
+DTEND;TZID=Europe/Berlin:20261005T170000\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,TU\r
+EXDATE;TZID=Europe/Berlin:20261027T090000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:rotation\r
+RECURRENCE-ID;TZID=Europe/Berlin:20261026T090000\r
+SUMMARY:bob\r
+DTSTART;TZID=Europe/Berlin:20261026T100000\r
+DTEND;TZID=Europe/Berlin:20261026T180000\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:single\r
+SUMMARY:carol\\, on call\r
+DTSTART;TZID=America/New_York:20261101T090000\r
+DURATION:PT8H\r
+ATTENDEE;CN=Carol:mailto:carol@example.com\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn reads_times_in_their_time_zone() {
+        let events = parse(ROTATION).unwrap();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].start, Some(utc("2026-10-05T07:00:00Z")));
+        assert_eq!(events[1].recurrence_id, Some(utc("2026-10-26T08:00:00Z")));
+        assert_eq!(events[2].summary, "carol, on call");
+        assert_eq!(events[2].attendees, vec!["carol@example.com"]);
+        assert_eq!(events[2].start, Some(utc("2026-11-01T14:00:00Z")));
+        assert_eq!(events[2].end, Some(utc("2026-11-01T22:00:00Z")));
+    }
+
+    #[test]
+    fn expands_recurring_events() {
+        let events = expand(
+            parse(ROTATION).unwrap(),
+            utc("2026-10-13T00:00:00Z"),
+            utc("2026-11-04T00:00:00Z"),
+        )
+        .unwrap();
+
+        let shifts: Vec<(&str, DateTime<Utc>)> = events
+            .iter()
+            .map(|event| (event.summary.as_str(), event.start.unwrap()))
+            .collect();
+        assert_eq!(
+            shifts,
+            vec![
+                ("alice", utc("2026-10-13T07:00:00Z")),
+                ("alice", utc("2026-10-19T07:00:00Z")),
+                ("alice", utc("2026-10-20T07:00:00Z")),
+                // 26th edited, 27th excluded, winter time from here on
+                ("alice", utc("2026-11-02T08:00:00Z")),
+                ("alice", utc("2026-11-03T08:00:00Z")),
+                ("bob", utc("2026-10-26T09:00:00Z")),
+                ("carol, on call", utc("2026-11-01T14:00:00Z")),
+            ]
+        );
+        assert_eq!(events[3].end, Some(utc("2026-11-02T16:00:00Z")));
+    }
+
+    #[test]
+    fn refuses_unknown_time_zones() {
+        let calendar = ROTATION.replace("Europe/Berlin", "Mars/Olympus");
+
+        assert!(parse(&calendar).unwrap_err().contains("Mars/Olympus"));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("P1W"), Some(Duration::weeks(1)));
+        assert_eq!(
+            parse_duration("PT8H30M"),
+            Some(Duration::minutes(8 * 60 + 30))
+        );
+        assert_eq!(parse_duration("P1DT1S"), Some(Duration::seconds(86401)));
+        assert_eq!(parse_duration("1D"), None);
+        assert_eq!(parse_duration("P99999999999999D"), None);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::core::profile;
+use crate::error::*;
+use crate::shared;
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::certificates::v1::CertificateSigningRequest;
+use k8s_openapi::api::rbac::v1::{
+    ClusterRole, ClusterRoleBinding, Role, RoleBinding, RoleRef, Subject,
+};
+use k8s_openapi::jiff::{SignedDuration, Timestamp};
+use kube::api::{DeleteParams, ListParams};
+use kube::{Api, Client, ResourceExt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagedKind {
+    ClusterRoleBinding,
+    RoleBinding,
+    ClusterRole,
+    Role,
+    CertificateSigningRequest,
+}
+
+impl ManagedKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ManagedKind::ClusterRoleBinding => "ClusterRoleBinding",
+            ManagedKind::RoleBinding => "RoleBinding",
+            ManagedKind::ClusterRole => "ClusterRole",
+            ManagedKind::Role => "Role",
+            ManagedKind::CertificateSigningRequest => "CertificateSigningRequest",
+        }
+    }
+}
+
+/// An object found in the cluster carrying the coralgate label
+#[derive(Debug, Clone)]
+pub struct ManagedObject {
+    pub kind: ManagedKind,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub profile: Option<String>,
+    pub role_ref: Option<RoleRef>,
+    /// Only set for bindings
+    pub subjects: Vec<Subject>,
+    /// Only set for CSRs, whether the issued certificate is still valid
+    pub active: bool,
+    /// When a temporary binding has to go
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl fmt::Display for ManagedObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.namespace {
+            Some(namespace) => write!(f, "{}/{} ({})", self.kind.as_str(), self.name, namespace),
+            None => write!(f, "{}/{}", self.kind.as_str(), self.name),
+        }
+    }
+}
+
+impl ManagedObject {
+    fn new<K: ResourceExt>(kind: ManagedKind, object: &K) -> Self {
+        Self {
+            kind,
+            name: object.name_any(),
+            namespace: object.namespace(),
+            profile: object.labels().get(shared::PROFILE_LABEL).cloned(),
+            role_ref: None,
+            subjects: vec![],
+            active: false,
+            expires_at: object
+                .annotations()
+                .get(shared::EXPIRES_ANNOTATION)
+                .and_then(|expires_at| expires_at.parse().ok()),
+        }
+    }
+
+    fn binding<K: ResourceExt>(
+        kind: ManagedKind,
+        binding: &K,
+        role_ref: RoleRef,
+        subjects: Option<Vec<Subject>>,
+    ) -> Self {
+        Self {
+            role_ref: Some(role_ref),
+            subjects: subjects.unwrap_or_default(),
+            ..Self::new(kind, binding)
+        }
+    }
+
+    /// Whether the binding names the exact user, labels only hold a
+    /// sanitized form several users may share
+    pub fn binds_user(&self, user: &str) -> bool {
+        self.subjects
+            .iter()
+            .any(|subject| subject.kind == "User" && subject.name == user)
+    }
+
+    /// Name of the profile the object belongs to, as the ledger records it.
+    /// Bindings of namespaced profiles are labelled with the profile kind,
+    /// `namespaced-edit` in `prod` is the profile `edit-prod`
+    pub fn profile_name(&self) -> Option<String> {
+        let label = self.profile.as_ref()?;
+
+        match &self.namespace {
+            Some(namespace) if profile::is_namespaced(label) => {
+                profile::resolve(label, Some(namespace))
+                    .ok()
+                    .map(|profile| profile.name().to_string())
+            }
+            _ => Some(label.clone()),
+        }
+    }
+
+    /// Temporary bindings past their expiry
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    /// Whether this object is a role referenced by the given binding
+    fn is_referenced_by(&self, binding: &ManagedObject) -> bool {
+        let Some(role_ref) = &binding.role_ref else {
+            return false;
+        };
+
+        match self.kind {
+            ManagedKind::ClusterRole => {
+                role_ref.kind == "ClusterRole" && role_ref.name == self.name
+            }
+            ManagedKind::Role => {
+                role_ref.kind == "Role"
+                    && role_ref.name == self.name
+                    && binding.namespace == self.namespace
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Lists every object coralgate created
+pub async fn collect(client: &Client) -> Result<Vec<ManagedObject>> {
+    let params = ListParams::default().labels(&shared::label_selector(None));
+    let mut objects = Vec::new();
+
+    let api: Api<ClusterRoleBinding> = Api::all(client.clone());
+    for binding in api.list(&params).await? {
+        objects.push(ManagedObject::binding(
+            ManagedKind::ClusterRoleBinding,
+            &binding,
+            binding.role_ref.clone(),
+            binding.subjects.clone(),
+        ));
+    }
+
+    let api: Api<RoleBinding> = Api::all(client.clone());
+    for binding in api.list(&params).await? {
+        objects.push(ManagedObject::binding(
+            ManagedKind::RoleBinding,
+            &binding,
+            binding.role_ref.clone(),
+            binding.subjects.clone(),
+        ));
+    }
+
+    let api: Api<ClusterRole> = Api::all(client.clone());
+    for role in api.list(&params).await? {
+        objects.push(ManagedObject::new(ManagedKind::ClusterRole, &role));
+    }
+
+    let api: Api<Role> = Api::all(client.clone());
+    for role in api.list(&params).await? {
+        objects.push(ManagedObject::new(ManagedKind::Role, &role));
+    }
+
+    let api: Api<CertificateSigningRequest> = Api::all(client.clone());
+    for csr in api.list(&params).await? {
+        let mut object = ManagedObject::new(ManagedKind::CertificateSigningRequest, &csr);
+        object.active = is_active(&csr);
+        objects.push(object);
+    }
+
+    Ok(objects)
+}
+
+/// Bindings coralgate created for a single user
+pub async fn user_bindings(client: &Client, user: &str) -> Result<Vec<ManagedObject>> {
+    let selector = format!(
+        "{},{}",
+        shared::label_selector(None),
+        shared::user_selector(user)
+    );
+
+    let mut bindings = bindings(client, &selector).await?;
+    bindings.retain(|binding| binding.binds_user(user));
+
+    Ok(bindings)
+}
+
+/// Temporary bindings made by `elevate`, expired or not
+pub async fn elevations(client: &Client) -> Result<Vec<ManagedObject>> {
+    let selector = format!(
+        "{},{}=true",
+        shared::label_selector(None),
+        shared::ELEVATION_LABEL
+    );
+
+    bindings(client, &selector).await
+}
+
+/// Bindings made by `schedule reconcile` for one schedule
+pub async fn scheduled(client: &Client, schedule: &str) -> Result<Vec<ManagedObject>> {
+    let selector = format!(
+        "{},{}={}",
+        shared::label_selector(None),
+        shared::SCHEDULE_LABEL,
+        schedule
+    );
+
+    bindings(client, &selector).await
+}
+
+async fn bindings(client: &Client, selector: &str) -> Result<Vec<ManagedObject>> {
+    let params = ListParams::default().labels(selector);
+    let mut objects = Vec::new();
+
+    let api: Api<ClusterRoleBinding> = Api::all(client.clone());
+    for binding in api.list(&params).await? {
+        objects.push(ManagedObject::binding(
+            ManagedKind::ClusterRoleBinding,
+            &binding,
+            binding.role_ref.clone(),
+            binding.subjects.clone(),
+        ));
+    }
+
+    let api: Api<RoleBinding> = Api::all(client.clone());
+    for binding in api.list(&params).await? {
+        objects.push(ManagedObject::binding(
+            ManagedKind::RoleBinding,
+            &binding,
+            binding.role_ref.clone(),
+            binding.subjects.clone(),
+        ));
+    }
+
+    Ok(objects)
+}
+
+/// A CSR backs an active grant when it was issued and its expiration has not passed
+fn is_active(csr: &CertificateSigningRequest) -> bool {
+    let issued = csr
+        .status
+        .as_ref()
+        .and_then(|status| status.certificate.as_ref())
+        .is_some();
+
+    let (Some(created), Some(seconds)) = (
+        csr.metadata.creation_timestamp.as_ref(),
+        csr.spec.expiration_seconds,
+    ) else {
+        return issued;
+    };
+
+    let expires_at = created.0 + SignedDuration::from_secs(seconds.into());
+    issued && expires_at > Timestamp::now()
+}
+
+/// Drops bindings whose profile still has unexpired certificates and unexpired
+/// temporary bindings, together with the roles they reference and the CSRs backing them.
+/// `granted` are profiles with active grants in the ledger, whose CSRs may be gone
+pub fn retain_inactive(
+    objects: Vec<ManagedObject>,
+    granted: &BTreeSet<String>,
+) -> (Vec<ManagedObject>, Vec<ManagedObject>) {
+    let active_profiles: BTreeSet<String> = objects
+        .iter()
+        .filter(|object| object.active)
+        .filter_map(|object| object.profile_name())
+        .chain(granted.iter().cloned())
+        .collect();
+
+    let is_kept_binding = |object: &ManagedObject| {
+        let unexpired = object.expires_at.is_some() && !object.is_expired();
+
+        matches!(
+            object.kind,
+            ManagedKind::ClusterRoleBinding | ManagedKind::RoleBinding
+        ) && (unexpired
+            || object
+                .profile_name()
+                .is_some_and(|profile| active_profiles.contains(&profile)))
+    };
+
+    let kept_bindings: Vec<ManagedObject> = objects
+        .iter()
+        .filter(|object| is_kept_binding(object))
+        .cloned()
+        .collect();
+
+    objects.into_iter().partition(|object| {
+        !(object.active
+            || is_kept_binding(object)
+            || kept_bindings
+                .iter()
+                .any(|binding| object.is_referenced_by(binding)))
+    })
+}
+
+pub async fn delete(client: &Client, object: &ManagedObject) -> Result<()> {
+    let params = DeleteParams::default();
+    let namespace = || {
+        object
+            .namespace
+            .as_deref()
+            .ok_or(CoralGateError::MissingNamespace(
+                object.kind.as_str().into(),
+            ))
+    };
+
+    match object.kind {
+        ManagedKind::ClusterRoleBinding => {
+            let api: Api<ClusterRoleBinding> = Api::all(client.clone());
+            api.delete(&object.name, &params).await?;
+        }
+        ManagedKind::RoleBinding => {
+            let api: Api<RoleBinding> = Api::namespaced(client.clone(), namespace()?);
+            api.delete(&object.name, &params).await?;
+        }
+        ManagedKind::ClusterRole => {
+            let api: Api<ClusterRole> = Api::all(client.clone());
+            api.delete(&object.name, &params).await?;
+        }
+        ManagedKind::Role => {
+            let api: Api<Role> = Api::namespaced(client.clone(), namespace()?);
+            api.delete(&object.name, &params).await?;
+        }
+        ManagedKind::CertificateSigningRequest => {
+            let api: Api<CertificateSigningRequest> = Api::all(client.clone());
+            api.delete(&object.name, &params).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn user_binding(user: &str) -> ManagedObject {
+        let binding = RoleBinding {
+            metadata: ObjectMeta {
+                name: Some(format!("edit-prod-{}", shared::sanitize_name(user))),
+                namespace: Some("prod".into()),
+                ..Default::default()
+            },
+            subjects: Some(vec![Subject {
+                kind: "User".into(),
+                name: user.into(),
+                api_group: Some("rbac.authorization.k8s.io".into()),
+                namespace: None,
+            }]),
+            role_ref: RoleRef {
+                kind: "ClusterRole".into(),
+                name: "edit".into(),
+                api_group: "rbac.authorization.k8s.io".into(),
+            },
+        };
+
+        ManagedObject::binding(
+            ManagedKind::RoleBinding,
+            &binding,
+            binding.role_ref.clone(),
+            binding.subjects.clone(),
+        )
+    }
+
+    #[test]
+    fn keeps_bindings_of_active_namespaced_grants() {
+        let mut binding = user_binding("bob");
+        binding.profile = Some("namespaced-edit".into());
+        let mut other = user_binding("carol");
+        other.namespace = Some("staging".into());
+        other.profile = Some("namespaced-edit".into());
+
+        let granted = BTreeSet::from([profile::namespaced_edit("prod").name().to_string()]);
+        let (removed, kept) = retain_inactive(vec![binding, other], &granted);
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].namespace.as_deref(), Some("prod"));
+        assert_eq!(removed[0].namespace.as_deref(), Some("staging"));
+    }
+
+    #[test]
+    fn matches_the_exact_user_only() {
+        let binding = user_binding("bob-smith");
+
+        assert!(binding.binds_user("bob-smith"));
+        assert!(!binding.binds_user("bob.smith"));
+        assert!(!user_binding("Alice").binds_user("alice"));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::command::structure::BindingMode;
+use crate::core::client::ClientManager;
+use crate::core::csr::SignerOptions;
+use crate::core::ledger::{self, Grant};
+use crate::core::local_ca::LocalCa;
+use crate::core::profile::{ApplyOptions, Profile, SubjectBinding};
+use crate::core::rollback::{self, Undo};
+use crate::core::{certificate, csr, revocation, verify};
+use crate::error::*;
+use crate::output::say;
+use crate::shared;
+
+use base64::Engine;
+use base64::engine::general_purpose;
+use k8s_openapi::api::certificates::v1::CertificateSigningRequest;
+use kube::Client;
+use tracing::{Instrument, info_span, instrument, warn};
+
+/// Everything needed to issue one credential
+pub struct IssueRequest {
+    pub user: String,
+    pub profile: Profile,
+    /// Namespace of a namespaced profile, kept in the ledger
+    pub namespace: Option<String>,
+    pub binding_mode: BindingMode,
+    pub expiration_seconds: i32,
+    /// Why the credential was issued, kept on the CSR and in the ledger
+    pub reason: Option<String>,
+    pub break_glass: bool,
+    pub skip_verify: bool,
+    pub signer: SignerOptions,
+    /// How long the CSR stays after the grant is recorded, zero deletes it
+    /// right away and `gc` removes it later otherwise
+    pub csr_retention: chrono::Duration,
+}
+
+impl IssueRequest {
+    /// Refuses what would fail halfway, before anything is created
+    pub fn validate(&self) -> Result<()> {
+        self.signer.validate()?;
+
+        // CSRs and files are named after the user
+        if shared::sanitize_name(&self.user).is_empty() {
+            return Err(CoralGateError::InvalidUser(self.user.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Group the certificate carries, none when the user is bound directly
+    pub fn group(&self) -> Option<&str> {
+        match self.binding_mode {
+            BindingMode::Group => Some(self.profile.group()),
+            BindingMode::User => None,
+        }
+    }
+}
+
+pub struct Issued {
+    pub kubeconfig: String,
+    pub grant: Grant,
+}
+
+/// Signs a certificate through a CSR, binds the user when asked to, verifies
+/// the kubeconfig against the cluster and records the grant
+#[instrument(
+    skip_all,
+    fields(
+        user = %request.user,
+        profile = request.profile.name(),
+        cluster = client_manager.cluster_url().ok(),
+    )
+)]
+pub async fn issue(
+    client: &Client,
+    client_manager: &ClientManager,
+    request: &IssueRequest,
+) -> Result<Issued> {
+    request.validate()?;
+
+    revocation::prune(client).await?;
+    let revoked = revocation::list(client).await?;
+    if revoked
+        .iter()
+        .any(|revocation| revocation.user == request.user)
+    {
+        return Err(CoralGateError::Revoked(request.user.clone()));
+    }
+
+    let csr_api: kube::Api<CertificateSigningRequest> = kube::Api::all(client.clone());
+    // Housekeeping, a CSR that cannot be removed now is left to gc
+    for (stale, reason) in csr::cleanup_candidates(&csr_api, Some(&request.user)).await? {
+        match csr::delete(&csr_api, &stale).await {
+            Ok(()) => say!("removed {} CSR {}", reason, stale),
+            Err(error) => warn!(csr = %stale, %error, "could not remove {} CSR", reason),
+        }
+    }
+
+    let self_signed_cert = csr::generate_certificate(&request.user, request.group()).await?;
+
+    let csr_object = csr::generate_cert_sigining_request_object(request, &self_signed_cert)?;
+
+    let created_csr = csr::create(&csr_object, &csr_api).await?;
+    let name = created_csr
+        .metadata
+        .name
+        .clone()
+        .ok_or_else(|| CoralGateError::MissingName("CertificateSigningRequest".into()))?;
+    // Recorded before approving, a denied or cancelled approval must not leave it behind
+    rollback::record(Undo::Csr(name.clone()));
+
+    if request.signer.approve {
+        csr::approve(&created_csr, &csr_api).await?;
+    }
+
+    let signed_cert = csr::get_signed_certificate(&name, &csr_api, request.signer.wait).await?;
+
+    // Custom signers may not honour every requested usage
+    let leaf = certificate::pem_to_der(&signed_cert.0)?.remove(0);
+    let missing = certificate::missing_usages(&leaf, &request.signer.usages)?;
+    if !missing.is_empty() {
+        return Err(CoralGateError::MissingUsages(missing.join(", ")));
+    }
+
+    let user_bindings = match request.binding_mode {
+        BindingMode::Group => vec![],
+        BindingMode::User => request
+            .profile
+            .subject_bindings(&SubjectBinding::user(&request.user)),
+    };
+    for binding in &user_bindings {
+        binding
+            .apply(client, &ApplyOptions::default())
+            .instrument(info_span!("bind", binding = %binding.id()))
+            .await?;
+    }
+
+    let signed_cert_b64 = general_purpose::STANDARD.encode(&signed_cert.0);
+    let private_key_b64 = general_purpose::STANDARD.encode(self_signed_cert.key_pem.as_bytes());
+
+    let kubeconfig_yaml = kubeconfig(
+        &client_manager.root_cert_base64()?,
+        &client_manager.cluster_url()?,
+        &request.user,
+        &signed_cert_b64,
+        &private_key_b64,
+    );
+
+    if !request.skip_verify {
+        let roots = client_manager.get_root_cert()?;
+        verify::verify_kubeconfig(
+            &kubeconfig_yaml,
+            &request.user,
+            request.group(),
+            &request.profile,
+            &signed_cert.0,
+            roots,
+        )
+        .await?;
+    }
+
+    let issued = certificate::summary(&leaf)?;
+    let mut grant = Grant {
+        id: String::new(),
+        user: request.user.clone(),
+        groups: issued.groups,
+        profile: request.profile.name().into(),
+        namespace: request.namespace.clone(),
+        issued_at: chrono::Utc::now(),
+        expires_at: issued.not_after,
+        csr: name.clone(),
+        serial: issued.serial,
+        reason: request.reason.clone(),
+        binding_mode: request.binding_mode,
+        break_glass: request.break_glass,
+    };
+    grant.id = ledger::record(client, &grant).await?;
+
+    // The ledger holds the grant now, the CSR only repeats the identity
+    if request.csr_retention.is_zero()
+        && let Err(error) = csr::delete(&csr_api, &name).await
+    {
+        warn!(csr = %name, %error, "could not delete CSR, gc will retry");
+    }
+
+    Ok(Issued {
+        kubeconfig: kubeconfig_yaml,
+        grant,
+    })
+}
+
+/// Signs with a CA key on disk instead of the certificates api. Nothing is
+/// sent to the cluster: no revocation check, no verification, no ledger
+/// record, so the returned grant has no id
+#[instrument(skip_all, fields(user = %request.user, profile = request.profile.name(), cluster = cluster_url))]
+pub async fn issue_offline(
+    local_ca: &LocalCa,
+    cluster_url: &str,
+    request: &IssueRequest,
+) -> Result<Issued> {
+    if request.binding_mode == BindingMode::User {
+        return Err(CoralGateError::OfflineUnsupported(
+            "--binding-mode user".into(),
+        ));
+    }
+
+    let generated = csr::generate_certificate(&request.user, request.group()).await?;
+    let signed_cert = local_ca.sign(&generated, request.expiration_seconds)?;
+
+    let kubeconfig_yaml = kubeconfig(
+        &general_purpose::STANDARD.encode(local_ca.cert_pem.as_bytes()),
+        cluster_url,
+        &request.user,
+        &general_purpose::STANDARD.encode(signed_cert.as_bytes()),
+        &general_purpose::STANDARD.encode(generated.key_pem.as_bytes()),
+    );
+
+    let leaf = certificate::pem_to_der(signed_cert.as_bytes())?.remove(0);
+    let issued = certificate::summary(&leaf)?;
+    let grant = Grant {
+        id: String::new(),
+        user: request.user.clone(),
+        groups: issued.groups,
+        profile: request.profile.name().into(),
+        namespace: request.namespace.clone(),
+        issued_at: chrono::Utc::now(),
+        expires_at: issued.not_after,
+        csr: String::new(),
+        serial: issued.serial,
+        reason: request.reason.clone(),
+        binding_mode: request.binding_mode,
+        break_glass: request.break_glass,
+    };
+
+    Ok(Issued {
+        kubeconfig: kubeconfig_yaml,
+        grant,
+    })
+}
+
+fn kubeconfig(root_ca: &str, cluster_url: &str, user: &str, cert: &str, key: &str) -> String {
+    format!(
+        r#"apiVersion: v1
+kind: Config
+clusters:
+- cluster:
+    certificate-authority-data: {root_ca}
+    server: {cluster_url}
+  name: cluster-default
+contexts:
+- context:
+    cluster: cluster-default
+    user: {user}
+  name: {user}-context
+current-context: {user}-context
+users:
+- name: {user}
+  user:
+    client-certificate-data: {cert}
+    client-key-data: {key}
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::profile;
+
+    use kube::config::Kubeconfig;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose};
+
+    const CLUSTER_URL: &str = "https://127.0.0.1:6443";
+
+    /// A throwaway CA written to disk, the way `--ca-cert` and `--ca-key` take it
+    fn local_ca(name: &str) -> (LocalCa, Vec<u8>) {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "coralgate-test-ca");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign];
+        let cert = params.self_signed(&key).unwrap();
+
+        let directory =
+            std::env::temp_dir().join(format!("coralgate-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let cert_path = directory.join("ca.crt");
+        let key_path = directory.join("ca.key");
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key.serialize_pem()).unwrap();
+
+        let local_ca =
+            LocalCa::read(cert_path.to_str().unwrap(), key_path.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+
+        (local_ca, cert.der().to_vec())
+    }
+
+    fn request(binding_mode: BindingMode) -> IssueRequest {
+        IssueRequest {
+            user: "alice".into(),
+            profile: profile::cluster_readonly_profile(),
+            namespace: None,
+            binding_mode,
+            expiration_seconds: 3600,
+            reason: Some("test".into()),
+            break_glass: false,
+            skip_verify: false,
+            signer: SignerOptions::default(),
+            csr_retention: chrono::Duration::zero(),
+        }
+    }
+
+    #[tokio::test]
+    async fn issues_offline_with_a_local_ca() {
+        let (local_ca, root) = local_ca("issues-offline");
+        let request = request(BindingMode::Group);
+
+        let issued = issue_offline(&local_ca, CLUSTER_URL, &request)
+            .await
+            .unwrap();
+
+        let kubeconfig = Kubeconfig::from_yaml(&issued.kubeconfig).unwrap();
+        let cluster = kubeconfig.clusters[0].cluster.as_ref().unwrap();
+        assert_eq!(cluster.server.as_deref(), Some(CLUSTER_URL));
+
+        let leaf = certificate::client_certificate(&kubeconfig).await.unwrap();
+        certificate::verify_chain(&leaf, &[], &[root]).unwrap();
+
+        let summary = certificate::summary(&leaf).unwrap();
+        assert_eq!(summary.user, "alice");
+        assert_eq!(summary.groups, vec![request.profile.group().to_string()]);
+        let validity = summary.not_after - summary.not_before;
+        assert_eq!(validity.num_seconds(), 3600 + 300);
+        assert!(summary.not_before <= chrono::Utc::now());
+
+        assert!(
+            certificate::missing_usages(&leaf, &["client auth".into()])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(issued.grant.user, "alice");
+        assert_eq!(issued.grant.serial, summary.serial);
+        assert_eq!(issued.grant.expires_at, summary.not_after);
+        assert!(issued.grant.id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_user_bindings_offline() {
+        let (local_ca, _) = local_ca("user-bindings-offline");
+
+        let result = issue_offline(&local_ca, CLUSTER_URL, &request(BindingMode::User)).await;
+
+        assert!(matches!(result, Err(CoralGateError::OfflineUnsupported(_))));
+    }
+
+    #[test]
+    fn refuses_users_without_a_name_for_their_objects() {
+        let mut request = request(BindingMode::Group);
+        assert!(request.validate().is_ok());
+
+        request.user = "...".into();
+        assert!(matches!(
+            request.validate(),
+            Err(CoralGateError::InvalidUser(_))
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::command::structure::BindingMode;
+use crate::core::certificate;
+use crate::core::rollback::{self, Undo};
+use crate::error::*;
+use crate::shared;
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::certificates::v1::CertificateSigningRequest;
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{DeleteParams, ListParams, PostParams};
+use kube::{Api, Client};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const LEDGER_LABEL: &str = "coralgate/ledger";
+const GRANT_KEY: &str = "grant.json";
+
+/// A credential coralgate issued, one ConfigMap per grant in the coralgate namespace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Grant {
+    /// Name of the ConfigMap holding the grant
+    #[serde(skip)]
+    pub id: String,
+    pub user: String,
+    pub groups: Vec<String>,
+    pub profile: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub csr: String,
+    /// Serial number of the issued certificate
+    pub serial: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Grants recorded before binding modes existed all used the group binding
+    #[serde(default)]
+    pub binding_mode: BindingMode,
+    /// Emergency access issued with `break-glass`
+    #[serde(default)]
+    pub break_glass: bool,
+}
+
+impl Grant {
+    pub fn is_active(&self) -> bool {
+        self.expires_at > Utc::now()
+    }
+}
+
+/// Creates the coralgate namespace when it is missing
+pub async fn ensure_namespace(client: &Client) -> Result<()> {
+    let api: Api<Namespace> = Api::all(client.clone());
+    if api.get_opt(shared::NAMESPACE).await?.is_some() {
+        return Ok(());
+    }
+
+    let namespace = Namespace {
+        metadata: ObjectMeta {
+            name: Some(shared::NAMESPACE.into()),
+            labels: shared::generate_lables(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    match api.create(&PostParams::default(), &namespace).await {
+        Ok(_) => {
+            rollback::record(Undo::Namespace(shared::NAMESPACE.into()));
+            Ok(())
+        }
+        Err(kube::Error::Api(status)) if status.code == 409 => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Stores the grant and returns its id
+#[tracing::instrument(name = "record_grant", skip_all, fields(serial = %grant.serial))]
+pub async fn record(client: &Client, grant: &Grant) -> Result<String> {
+    ensure_namespace(client).await?;
+
+    let mut labels = shared::generate_profile_lables(&grant.profile).unwrap_or_default();
+    labels.insert(LEDGER_LABEL.into(), "grant".into());
+    labels.insert(
+        shared::USER_LABEL.into(),
+        shared::sanitize_name(&grant.user),
+    );
+    if grant.break_glass {
+        labels.insert(shared::BREAK_GLASS_LABEL.into(), "true".into());
+    }
+
+    let data = serde_json::to_string_pretty(grant).unwrap_or_default();
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            generate_name: Some(format!("grant-{}-", shared::sanitize_name(&grant.user))),
+            namespace: Some(shared::NAMESPACE.into()),
+            labels: Some(labels),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(GRANT_KEY.into(), data)])),
+        ..Default::default()
+    };
+
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+    let created = api.create(&PostParams::default(), &config_map).await?;
+    let id = created.metadata.name.unwrap_or_default();
+    rollback::record(Undo::Grant(id.clone()));
+    tracing::info!(grant = %id, "recorded grant");
+
+    Ok(id)
+}
+
+/// Every recorded grant, optionally only the ones of a user
+pub async fn list(client: &Client, user: Option<&str>) -> Result<Vec<Grant>> {
+    let mut selector = format!("{},{}=grant", shared::label_selector(None), LEDGER_LABEL);
+    if let Some(user) = user {
+        selector.push_str(&format!(
+            ",{}={}",
+            shared::USER_LABEL,
+            shared::sanitize_name(user)
+        ));
+    }
+
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+    let config_maps = match api.list(&ListParams::default().labels(&selector)).await {
+        Ok(config_maps) => config_maps,
+        Err(kube::Error::Api(status)) if status.is_not_found() => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut grants = Vec::new();
+    for config_map in config_maps {
+        let Some(data) = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get(GRANT_KEY))
+        else {
+            continue;
+        };
+
+        let Ok(mut grant) = serde_json::from_str::<Grant>(data) else {
+            continue;
+        };
+
+        // The label is sanitized, the exact name lives in the grant
+        if user.is_some_and(|user| user != grant.user) {
+            continue;
+        }
+
+        grant.id = config_map.metadata.name.unwrap_or_default();
+        grants.push(grant);
+    }
+
+    grants.sort_by_key(|grant| grant.issued_at);
+    Ok(grants)
+}
+
+pub async fn delete(client: &Client, id: &str) -> Result<()> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+
+    match api.delete(id, &DeleteParams::default()).await {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(status)) if status.is_not_found() => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Grants reconstructed from issued coralgate CSRs, for credentials issued
+/// before the ledger existed or when it was lost
+pub async fn from_csrs(client: &Client) -> Result<Vec<Grant>> {
+    let api: Api<CertificateSigningRequest> = Api::all(client.clone());
+    let params = ListParams::default().labels(&shared::label_selector(None));
+
+    let mut grants = Vec::new();
+    for csr in api.list(&params).await? {
+        let Some(pem) = csr
+            .status
+            .as_ref()
+            .and_then(|status| status.certificate.as_ref())
+        else {
+            continue;
+        };
+
+        let Ok(issued) =
+            certificate::pem_to_der(&pem.0).and_then(|chain| certificate::summary(&chain[0]))
+        else {
+            continue;
+        };
+
+        let issued_at = csr
+            .metadata
+            .creation_timestamp
+            .as_ref()
+            .and_then(|created| DateTime::from_timestamp(created.0.as_second(), 0))
+            .unwrap_or(issued.not_before);
+
+        let labels = csr.metadata.labels.as_ref();
+
+        // Certificates for per-user bindings carry no group
+        let binding_mode = if issued.groups.is_empty() {
+            BindingMode::User
+        } else {
+            BindingMode::Group
+        };
+
+        grants.push(Grant {
+            id: String::new(),
+            user: issued.user,
+            groups: issued.groups,
+            profile: labels
+                .and_then(|labels| labels.get(shared::PROFILE_LABEL))
+                .cloned()
+                .unwrap_or_default(),
+            namespace: None,
+            issued_at,
+            expires_at: issued.not_after,
+            csr: csr.metadata.name.clone().unwrap_or_default(),
+            binding_mode,
+            break_glass: labels
+                .and_then(|labels| labels.get(shared::BREAK_GLASS_LABEL))
+                .is_some_and(|value| value == "true"),
+            serial: issued.serial,
+            reason: csr
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(shared::REASON_ANNOTATION))
+                .cloned(),
+        });
+    }
+
+    Ok(grants)
+}
+
+/// The ledger completed with issued CSRs it does not know about
+pub async fn list_with_csrs(client: &Client, user: Option<&str>) -> Result<Vec<Grant>> {
+    let mut grants = list(client, user).await?;
+
+    for grant in from_csrs(client).await? {
+        let known = grants.iter().any(|known| known.csr == grant.csr);
+        if !known && user.is_none_or(|user| user == grant.user) {
+            grants.push(grant);
+        }
+    }
+
+    grants.sort_by_key(|grant| grant.issued_at);
+    Ok(grants)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRANT: &str = r#"{
+        "user": "alice",
+        "groups": [],
+        "profile": "cluster-readonly",
+        "issuedAt": "2026-01-01T00:00:00Z",
+        "expiresAt": "2026-02-01T00:00:00Z",
+        "csr": "coralgate-alice-abcde",
+        "serial": "01"
+    }"#;
+
+    #[test]
+    fn grants_without_a_binding_mode_used_the_group() {
+        let grant: Grant = serde_json::from_str(GRANT).unwrap();
+
+        assert_eq!(grant.binding_mode, BindingMode::Group);
+    }
+
+    #[test]
+    fn stores_the_binding_mode_in_lowercase() {
+        let mut grant: Grant = serde_json::from_str(GRANT).unwrap();
+        grant.binding_mode = BindingMode::User;
+
+        let stored = serde_json::to_value(&grant).unwrap();
+        assert_eq!(stored["bindingMode"], "user");
+
+        let read: Grant = serde_json::from_value(stored).unwrap();
+        assert_eq!(read.binding_mode, BindingMode::User);
+    }
+}
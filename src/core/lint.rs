@@ -0,0 +1,420 @@
+use crate::core::profile::Profile;
+use crate::core::rbac::{self, Snapshot};
+
+use std::fmt;
+
+use k8s_openapi::api::rbac::v1::PolicyRule;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Medium => write!(f, "medium"),
+            Severity::High => write!(f, "high"),
+        }
+    }
+}
+
+/// A known escalation path a profile opens
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub profile: String,
+    pub id: &'static str,
+    pub severity: Severity,
+    /// Role carrying the rule
+    pub source: String,
+    pub rule: String,
+    pub explanation: &'static str,
+    /// The profile declares it grants this on purpose
+    pub acknowledged: bool,
+}
+
+struct Check {
+    id: &'static str,
+    severity: Severity,
+    explanation: &'static str,
+    matches: fn(&PolicyRule) -> bool,
+}
+
+const RBAC: &str = "rbac.authorization.k8s.io";
+
+const CHECKS: [Check; 13] = [
+    Check {
+        id: "wildcard-verbs",
+        severity: Severity::High,
+        explanation: "`*` verbs include escalate, bind and impersonate, and every verb added in the future",
+        matches: |rule| rule.verbs.iter().any(|verb| verb == "*"),
+    },
+    Check {
+        id: "wildcard-resources",
+        severity: Severity::High,
+        explanation: "`*` resources include secrets, RBAC objects and every resource added in the future",
+        matches: |rule| {
+            rule.resources
+                .iter()
+                .flatten()
+                .any(|resource| resource == "*")
+        },
+    },
+    Check {
+        id: "escalate",
+        severity: Severity::High,
+        explanation: "escalate allows writing roles with permissions the holder does not have",
+        matches: |rule| allows(rule, &["escalate"], &[RBAC], &["roles", "clusterroles"]),
+    },
+    Check {
+        id: "bind",
+        severity: Severity::High,
+        explanation: "bind allows binding any role, including cluster-admin, to any subject",
+        matches: |rule| allows(rule, &["bind"], &[RBAC], &["roles", "clusterroles"]),
+    },
+    Check {
+        id: "impersonate",
+        severity: Severity::High,
+        explanation: "impersonate allows acting as any user, group or service account",
+        matches: |rule| {
+            allows(
+                rule,
+                &["impersonate"],
+                &["", "authentication.k8s.io"],
+                &["users", "groups", "serviceaccounts", "uids", "userextras"],
+            )
+        },
+    },
+    Check {
+        id: "secrets-read",
+        severity: Severity::High,
+        explanation: "reading secrets exposes service account tokens and credentials of other workloads",
+        matches: |rule| allows(rule, &["get", "list", "watch"], &[""], &["secrets"]),
+    },
+    Check {
+        id: "pods-exec",
+        severity: Severity::High,
+        explanation: "exec and attach give a shell in running pods, with their service account and mounted secrets",
+        matches: |rule| {
+            allows(
+                rule,
+                &["create", "get"],
+                &[""],
+                &["pods/exec", "pods/attach"],
+            )
+        },
+    },
+    Check {
+        id: "serviceaccount-token",
+        severity: Severity::High,
+        explanation: "creating service account tokens allows acting as any service account of the namespace",
+        matches: |rule| allows(rule, &["create"], &[""], &["serviceaccounts/token"]),
+    },
+    Check {
+        id: "csr-approval",
+        severity: Severity::High,
+        explanation: "approving CSRs allows issuing client certificates for any user or group",
+        matches: |rule| {
+            allows(
+                rule,
+                &["update", "patch"],
+                &["certificates.k8s.io"],
+                &["certificatesigningrequests/approval"],
+            ) || allows(rule, &["approve"], &["certificates.k8s.io"], &["signers"])
+        },
+    },
+    Check {
+        id: "nodes-proxy",
+        severity: Severity::High,
+        explanation: "nodes/proxy reaches the kubelet api, which allows exec in every pod of the node",
+        matches: |rule| allows(rule, &["get", "create"], &[""], &["nodes/proxy"]),
+    },
+    Check {
+        id: "workload-create",
+        severity: Severity::Medium,
+        explanation: "creating workloads allows mounting any secret and running as any service account of the namespace",
+        matches: |rule| {
+            allows(rule, &["create"], &[""], &["pods"])
+                || allows(
+                    rule,
+                    &["create"],
+                    &["apps"],
+                    &["deployments", "daemonsets", "statefulsets", "replicasets"],
+                )
+                || allows(rule, &["create"], &["batch"], &["jobs", "cronjobs"])
+        },
+    },
+    Check {
+        id: "rbac-write",
+        severity: Severity::Medium,
+        explanation: "writing RBAC objects allows granting existing permissions to other subjects",
+        matches: |rule| {
+            allows(
+                rule,
+                &["create", "update", "patch"],
+                &[RBAC],
+                &[
+                    "roles",
+                    "clusterroles",
+                    "rolebindings",
+                    "clusterrolebindings",
+                ],
+            )
+        },
+    },
+    Check {
+        id: "webhook-write",
+        severity: Severity::Medium,
+        explanation: "admission webhooks see and can modify every matching request in the cluster",
+        matches: |rule| {
+            allows(
+                rule,
+                &["create", "update", "patch"],
+                &["admissionregistration.k8s.io"],
+                &[
+                    "mutatingwebhookconfigurations",
+                    "validatingwebhookconfigurations",
+                ],
+            )
+        },
+    },
+];
+
+/// Ids of every finding, for profiles that are meant to be all powerful
+pub fn all_findings() -> Vec<String> {
+    CHECKS.iter().map(|check| check.id.to_string()).collect()
+}
+
+/// Whether the rule grants any of the verbs on any of the resources, wildcards included
+fn allows(rule: &PolicyRule, verbs: &[&str], api_groups: &[&str], resources: &[&str]) -> bool {
+    let any_of = |values: &Option<Vec<String>>, wanted: &[&str]| {
+        values
+            .iter()
+            .flatten()
+            .any(|value| value == "*" || wanted.contains(&value.as_str()))
+    };
+
+    let verb = rule
+        .verbs
+        .iter()
+        .any(|verb| verb == "*" || verbs.contains(&verb.as_str()));
+
+    let resource = rule.resources.iter().flatten().any(|resource| {
+        resource == "*"
+            || resources.contains(&resource.as_str())
+            || resource.strip_suffix("/*").is_some_and(|parent| {
+                resources
+                    .iter()
+                    .any(|wanted| wanted.starts_with(&format!("{}/", parent)))
+            })
+    });
+
+    verb && any_of(&rule.api_groups, api_groups) && resource
+}
+
+fn lint_rules(profile: &Profile, source: &str, rules: &[PolicyRule], findings: &mut Vec<Finding>) {
+    for rule in rules {
+        for check in &CHECKS {
+            if !(check.matches)(rule) {
+                continue;
+            }
+
+            let duplicate = findings
+                .iter()
+                .any(|finding| finding.id == check.id && finding.source == source);
+            if duplicate {
+                continue;
+            }
+
+            findings.push(Finding {
+                profile: profile.name().into(),
+                id: check.id,
+                severity: check.severity,
+                source: source.into(),
+                rule: rbac::describe_rule(rule),
+                explanation: check.explanation,
+                acknowledged: profile.acknowledged.iter().any(|id| id == check.id),
+            });
+        }
+    }
+}
+
+/// Finds escalation paths in the profile's own roles and in the cluster roles
+/// its bindings reference
+pub fn lint_profile(profile: &Profile, snapshot: &Snapshot) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for resource in &profile.resources {
+        lint_rules(profile, &resource.id(), &resource.rules(), &mut findings);
+
+        let Some(role_ref) = resource.role_ref() else {
+            continue;
+        };
+
+        let source = format!("{}/{}", role_ref.kind, role_ref.name);
+        let shipped = profile
+            .resources
+            .iter()
+            .any(|resource| resource.id() == source);
+
+        // Roles shipped with the profile are linted on their own
+        if role_ref.kind == "ClusterRole" && !shipped {
+            let rules = snapshot.cluster_role_rules(&role_ref.name);
+            lint_rules(profile, &source, &rules, &mut findings);
+        }
+    }
+
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    findings
+}
+
+/// High severity findings the profile did not acknowledge
+pub fn blocking(findings: &[Finding]) -> Vec<&Finding> {
+    findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::High && !finding.acknowledged)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::profile::{self, ProfileDefinition};
+
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::rbac::v1::ClusterRole;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn rule(api_group: &str, resources: &[&str], verbs: &[&str]) -> PolicyRule {
+        PolicyRule {
+            api_groups: Some(vec![api_group.into()]),
+            resources: Some(
+                resources
+                    .iter()
+                    .map(|resource| resource.to_string())
+                    .collect(),
+            ),
+            verbs: verbs.iter().map(|verb| verb.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn definition(rules: Vec<PolicyRule>, acknowledged: &[&str]) -> Profile {
+        ProfileDefinition {
+            name: "test".into(),
+            namespaces: BTreeMap::from([("apps".into(), rules)]),
+            acknowledged: acknowledged.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        }
+        .into_profile()
+    }
+
+    fn ids(findings: &[Finding]) -> Vec<&str> {
+        findings.iter().map(|finding| finding.id).collect()
+    }
+
+    #[test]
+    fn finds_escalation_paths() {
+        let profile = definition(
+            vec![
+                rule("", &["secrets", "configmaps"], &["get"]),
+                rule("", &["pods/*"], &["create"]),
+                rule("apps", &["deployments"], &["create"]),
+            ],
+            &[],
+        );
+
+        let findings = lint_profile(&profile, &Snapshot::default());
+
+        assert_eq!(
+            ids(&findings),
+            vec!["secrets-read", "pods-exec", "workload-create"]
+        );
+        let blocking: Vec<&str> = blocking(&findings)
+            .iter()
+            .map(|finding| finding.id)
+            .collect();
+        assert_eq!(blocking, vec!["secrets-read", "pods-exec"]);
+    }
+
+    #[test]
+    fn leaves_harmless_rules_alone() {
+        let profile = definition(
+            vec![
+                rule("", &["pods", "pods/log"], &["get", "list", "watch"]),
+                rule("rbac.authorization.k8s.io", &["roles"], &["get"]),
+            ],
+            &[],
+        );
+
+        assert!(lint_profile(&profile, &Snapshot::default()).is_empty());
+    }
+
+    #[test]
+    fn acknowledged_findings_do_not_block() {
+        let profile = definition(vec![rule("", &["secrets"], &["list"])], &["secrets-read"]);
+
+        let findings = lint_profile(&profile, &Snapshot::default());
+
+        assert!(findings[0].acknowledged);
+        assert!(blocking(&findings).is_empty());
+    }
+
+    #[test]
+    fn follows_referenced_cluster_roles() {
+        let snapshot = Snapshot {
+            cluster_roles: vec![ClusterRole {
+                metadata: ObjectMeta {
+                    name: Some("cluster-admin".into()),
+                    ..Default::default()
+                },
+                rules: Some(vec![rule("*", &["*"], &["*"])]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let findings = lint_profile(&profile::admin_profile(), &snapshot);
+
+        assert!(ids(&findings).contains(&"wildcard-verbs"));
+        assert!(
+            findings
+                .iter()
+                .all(|finding| finding.source == "ClusterRole/cluster-admin")
+        );
+        assert!(blocking(&findings).is_empty());
+    }
+
+    #[tokio::test]
+    async fn lints_profiles_read_from_yaml() {
+        let path =
+            std::env::temp_dir().join(format!("coralgate-{}-profile.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            "name: debugger\n\
+             clusterRules:\n\
+             \x20 - { apiGroups: [\"\"], resources: [nodes/proxy], verbs: [get] }\n\
+             namespaces:\n\
+             \x20 prod:\n\
+             \x20   - { apiGroups: [\"\"], resources: [pods], verbs: [get, list] }\n",
+        )
+        .unwrap();
+
+        let profile = ProfileDefinition::read(path.to_str().unwrap())
+            .await
+            .unwrap()
+            .into_profile();
+        std::fs::remove_file(path).unwrap();
+
+        let findings = lint_profile(&profile, &Snapshot::default());
+
+        assert_eq!(ids(&findings), vec!["nodes-proxy"]);
+        assert_eq!(findings[0].profile, "debugger");
+        assert_eq!(findings[0].source, "ClusterRole/coralgate-debugger");
+        assert_eq!(blocking(&findings).len(), 1);
+    }
+}
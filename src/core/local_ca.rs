@@ -0,0 +1,68 @@
+use crate::core::certificate;
+use crate::core::csr::GeneratedCsrWithPem;
+use crate::error::*;
+
+use rcgen::{
+    CertificateSigningRequestParams, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose,
+};
+use time::OffsetDateTime;
+
+/// Client certificates start this long before now, for clocks slightly behind
+const BACKDATE_SECONDS: i64 = 300;
+
+/// A CA certificate and key on disk, like kubeadm's `/etc/kubernetes/pki/ca.{crt,key}`
+pub struct LocalCa {
+    pub cert_pem: String,
+    issuer: Issuer<'static, KeyPair>,
+}
+
+impl LocalCa {
+    pub fn read(cert_path: &str, key_path: &str) -> Result<LocalCa> {
+        let invalid = |path: &str, reason: String| CoralGateError::InvalidCa {
+            path: path.into(),
+            reason,
+        };
+
+        let cert_pem = std::fs::read_to_string(cert_path)?;
+        let key_pem = std::fs::read_to_string(key_path)?;
+
+        let key_pair =
+            KeyPair::from_pem(&key_pem).map_err(|error| invalid(key_path, error.to_string()))?;
+        let issuer = Issuer::from_ca_cert_pem(&cert_pem, key_pair)
+            .map_err(|error| invalid(cert_path, error.to_string()))?;
+
+        Ok(LocalCa { cert_pem, issuer })
+    }
+
+    /// Signs the request the way the kube-apiserver-client signer would: a
+    /// client auth certificate with the requested subject, valid from now on
+    pub fn sign(&self, generated: &GeneratedCsrWithPem, expiration_seconds: i32) -> Result<String> {
+        let csr_pem = generated
+            .csr
+            .pem()
+            .map_err(|error| CoralGateError::CertificateParseError(error.to_string()))?;
+        let mut request = CertificateSigningRequestParams::from_pem(&csr_pem)
+            .map_err(|error| CoralGateError::CertificateParseError(error.to_string()))?;
+
+        let now = OffsetDateTime::now_utc();
+        request.params.not_before = now - time::Duration::seconds(BACKDATE_SECONDS);
+        request.params.not_after = now + time::Duration::seconds(expiration_seconds.into());
+        request.params.is_ca = IsCa::ExplicitNoCa;
+        request.params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        request.params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+
+        let signed = request
+            .signed_by(&self.issuer)
+            .map_err(|error| CoralGateError::SigningFailed(error.to_string()))?;
+
+        // Catches a key that does not belong to the certificate
+        let roots = certificate::pem_to_der(self.cert_pem.as_bytes())?;
+        certificate::verify_chain(signed.der(), &[], &roots)?;
+
+        Ok(signed.pem())
+    }
+}
@@ -1,81 +1,589 @@
+use crate::command::structure::PermissionProfile;
+use crate::core::access::AccessCheck;
+use crate::core::lint;
+use crate::core::rollback::{self, Undo};
 use crate::error::*;
+use crate::shared;
 
-use k8s_openapi::api::rbac::v1::{ClusterRoleBinding, RoleBinding, RoleRef, Subject};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use k8s_openapi::api::admissionregistration::v1::{
+    ValidatingAdmissionPolicy, ValidatingAdmissionPolicyBinding,
+};
+use k8s_openapi::api::core::v1::{LimitRange, Namespace, ResourceQuota};
+use k8s_openapi::api::networking::v1::NetworkPolicy;
+use k8s_openapi::api::rbac::v1::{
+    ClusterRole, ClusterRoleBinding, PolicyRule, Role, RoleBinding, RoleRef, Subject,
+};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
-use kube::api::{Patch, PatchParams};
-use kube::{Api, Client};
+use kube::api::{DeleteParams, Patch, PatchParams};
+use kube::{Api, Client, Resource};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What applying a resource would change in the cluster
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Create,
+    Unchanged,
+    /// The object exists but coralgate does not own it
+    NotOwned,
+    /// Changed fields, as `path: old -> new`
+    Update(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceDiff {
+    pub resource: String,
+    pub change: Change,
+}
+
+/// Field managers that count as coralgate, `kaccess` was used by older releases
+const OWN_FIELD_MANAGERS: [&str; 2] = [shared::FIELD_MANAGER, "kaccess"];
+
+#[derive(Debug, Clone, Default)]
+pub struct ApplyOptions {
+    /// Take over objects coralgate did not create and overwrite fields other
+    /// managers own
+    pub adopt: bool,
+}
+
+/// What a resource contributes to the permissions of a profile
+pub trait Grants {
+    /// `Kind/name` of the resource
+    fn id(&self) -> String;
+
+    /// Rules a Role or ClusterRole carries
+    fn rules(&self) -> Vec<PolicyRule> {
+        vec![]
+    }
+
+    /// Role a binding points at
+    fn role_ref(&self) -> Option<&RoleRef> {
+        None
+    }
+
+    /// The same binding for a single subject, see [`SubjectBinding`]
+    fn bind(&self, _binding: &SubjectBinding) -> Option<Box<dyn Apply + Send + Sync>> {
+        None
+    }
+}
+
+/// A copy of a profile binding for one user or group, named
+/// `<binding>-<suffix>` and carrying extra labels and annotations
+#[derive(Debug, Clone)]
+pub struct SubjectBinding {
+    pub subject: Subject,
+    pub suffix: String,
+    pub labels: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl SubjectBinding {
+    pub fn user(user: &str) -> Self {
+        Self::new("User", user)
+    }
+
+    pub fn group(group: &str) -> Self {
+        Self::new("Group", group)
+    }
+
+    fn new(kind: &str, name: &str) -> Self {
+        // Unique per exact name, two subjects must never share a binding
+        let suffix = shared::unique_label(name);
+        let mut labels = BTreeMap::new();
+        if kind == "User" {
+            labels.insert(shared::USER_LABEL.into(), suffix.clone());
+        }
+
+        Self {
+            subject: Subject {
+                kind: kind.into(),
+                name: name.into(),
+                api_group: Some("rbac.authorization.k8s.io".into()),
+                namespace: None,
+            },
+            suffix,
+            labels,
+            annotations: BTreeMap::new(),
+        }
+    }
 
+    fn metadata(&self, metadata: &ObjectMeta) -> ObjectMeta {
+        let mut labels = metadata.labels.clone().unwrap_or_default();
+        labels.extend(self.labels.clone());
+
+        ObjectMeta {
+            name: Some(format!(
+                "{}-{}",
+                metadata.name.as_deref().unwrap_or_default(),
+                self.suffix
+            )),
+            namespace: metadata.namespace.clone(),
+            labels: Some(labels),
+            annotations: Some(self.annotations.clone())
+                .filter(|annotations| !annotations.is_empty()),
+            ..Default::default()
+        }
+    }
+}
+
+/// A kubernetes object coralgate manages, it is always labeled and applied
+/// server side with the coralgate field manager
 #[async_trait::async_trait]
-pub trait Apply {
-    async fn apply(&self, client: &kube::Client) -> Result<()>;
+pub trait Apply: Grants {
+    /// Refuses to touch an existing object coralgate does not own unless adopting
+    async fn apply(&self, client: &kube::Client, options: &ApplyOptions) -> Result<()>;
+
+    /// Deletes the object, an already missing object is not an error
+    async fn delete(&self, client: &kube::Client, options: &ApplyOptions) -> Result<()>;
+
+    /// Compares the object with what is in the cluster using a dry-run apply
+    async fn diff(&self, client: &kube::Client) -> Result<ResourceDiff>;
+
+    async fn exists(&self, client: &kube::Client) -> Result<bool>;
 }
 
 #[derive(Default)]
 pub struct Profile {
     name: String,
+    /// Group the issued certificates carry as organization
+    group: String,
     pub resources: Vec<Box<dyn Apply + Send + Sync>>,
+    /// What a member of the profile group must and must not be able to do
+    pub checks: Vec<AccessCheck>,
+    /// Lint findings the profile grants on purpose, by finding id
+    pub acknowledged: Vec<String>,
 }
 
 impl Profile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
     pub fn add_resource(&mut self, resource: Box<dyn Apply + Send + Sync>) {
         self.resources.push(resource);
     }
 
-    pub async fn apply(&self, client: &kube::Client) -> Result<()> {
+    #[tracing::instrument(name = "apply_profile", skip_all, fields(profile = self.name()))]
+    pub async fn apply(&self, client: &kube::Client, options: &ApplyOptions) -> Result<()> {
         for resource in &self.resources {
-            resource.apply(client).await?
+            resource.apply(client, options).await?
         }
 
         Ok(())
     }
+
+    pub async fn delete(&self, client: &kube::Client, options: &ApplyOptions) -> Result<()> {
+        for resource in self.resources.iter().rev() {
+            resource.delete(client, options).await?
+        }
+
+        Ok(())
+    }
+
+    pub async fn diff(&self, client: &kube::Client) -> Result<Vec<ResourceDiff>> {
+        let mut diffs = Vec::new();
+
+        for resource in &self.resources {
+            diffs.push(resource.diff(client).await?);
+        }
+
+        Ok(diffs)
+    }
+
+    /// Copies of the profile's bindings for a single subject, used instead of
+    /// the shared group binding with `--binding-mode user` and by `elevate`
+    pub fn subject_bindings(&self, binding: &SubjectBinding) -> Vec<Box<dyn Apply + Send + Sync>> {
+        self.resources
+            .iter()
+            .filter_map(|resource| resource.bind(binding))
+            .collect()
+    }
+
+    /// A profile is installed when every one of its resources exists
+    pub async fn exists(&self, client: &kube::Client) -> Result<bool> {
+        for resource in &self.resources {
+            if !resource.exists(client).await? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
 }
 
-#[async_trait::async_trait]
-impl Apply for ClusterRoleBinding {
-    async fn apply(&self, client: &Client) -> Result<()> {
-        let api: Api<ClusterRoleBinding> = Api::all(client.clone());
+/// Implements [`Apply`] for a kubernetes type, `cluster` or `namespaced`
+/// decides how the api is scoped
+macro_rules! impl_apply {
+    ($kind:ty, cluster) => {
+        impl_apply!(@impl $kind, |client: &Client, _resource: &$kind| -> Result<Api<$kind>> {
+            Ok(Api::all(client.clone()))
+        });
+    };
+    ($kind:ty, namespaced) => {
+        impl_apply!(@impl $kind, |client: &Client, resource: &$kind| -> Result<Api<$kind>> {
+            let namespace = resource
+                .metadata
+                .namespace
+                .as_ref()
+                .ok_or(CoralGateError::MissingNamespace(stringify!($kind).into()))?;
+
+            Ok(Api::namespaced(client.clone(), namespace))
+        });
+    };
+    (@impl $kind:ty, $api:expr) => {
+        #[async_trait::async_trait]
+        impl Apply for $kind {
+            async fn apply(&self, client: &Client, options: &ApplyOptions) -> Result<()> {
+                if apply_with(&$api(client, self)?, self, options).await? {
+                    rollback::record(Undo::Object(std::sync::Arc::new(self.clone())));
+                }
 
-        let name = self
-            .metadata
-            .name
-            .as_ref()
-            .ok_or(CoralGateError::MissingName("Cluster Role binding".into()))?;
+                Ok(())
+            }
 
-        api.patch(
-            name,
-            &PatchParams::apply("kaccess").force(),
-            &Patch::Apply(self),
-        )
-        .await?;
+            async fn delete(&self, client: &Client, options: &ApplyOptions) -> Result<()> {
+                delete_with(&$api(client, self)?, self, options).await
+            }
 
-        Ok(())
+            async fn diff(&self, client: &Client) -> Result<ResourceDiff> {
+                diff_with(&$api(client, self)?, self).await
+            }
+
+            async fn exists(&self, client: &Client) -> Result<bool> {
+                Ok($api(client, self)?.get_opt(resource_name(self)?).await?.is_some())
+            }
+        }
+    };
+}
+
+impl Grants for ClusterRole {
+    fn id(&self) -> String {
+        resource_id(self)
+    }
+
+    fn rules(&self) -> Vec<PolicyRule> {
+        self.rules.clone().unwrap_or_default()
     }
 }
 
-#[async_trait::async_trait]
-impl Apply for RoleBinding {
-    async fn apply(&self, client: &Client) -> Result<()> {
-        let namespace = self
-            .metadata
-            .namespace
-            .as_ref()
-            .ok_or(CoralGateError::MissingNamespace("Role binding".into()))?;
-
-        let api: Api<RoleBinding> = Api::namespaced(client.clone(), namespace);
-        let name = self
-            .metadata
-            .name
-            .as_ref()
-            .ok_or(CoralGateError::MissingName("Role Binding".into()))?;
-
-        api.patch(
+impl Grants for Role {
+    fn id(&self) -> String {
+        resource_id(self)
+    }
+
+    fn rules(&self) -> Vec<PolicyRule> {
+        self.rules.clone().unwrap_or_default()
+    }
+}
+
+impl Grants for ClusterRoleBinding {
+    fn id(&self) -> String {
+        resource_id(self)
+    }
+
+    fn role_ref(&self) -> Option<&RoleRef> {
+        Some(&self.role_ref)
+    }
+
+    fn bind(&self, binding: &SubjectBinding) -> Option<Box<dyn Apply + Send + Sync>> {
+        Some(Box::new(ClusterRoleBinding {
+            metadata: binding.metadata(&self.metadata),
+            subjects: Some(vec![binding.subject.clone()]),
+            role_ref: self.role_ref.clone(),
+        }))
+    }
+}
+
+impl Grants for RoleBinding {
+    fn id(&self) -> String {
+        resource_id(self)
+    }
+
+    fn role_ref(&self) -> Option<&RoleRef> {
+        Some(&self.role_ref)
+    }
+
+    fn bind(&self, binding: &SubjectBinding) -> Option<Box<dyn Apply + Send + Sync>> {
+        Some(Box::new(RoleBinding {
+            metadata: binding.metadata(&self.metadata),
+            subjects: Some(vec![binding.subject.clone()]),
+            role_ref: self.role_ref.clone(),
+        }))
+    }
+}
+
+macro_rules! impl_grants_none {
+    ($($kind:ty),*) => {
+        $(
+            impl Grants for $kind {
+                fn id(&self) -> String {
+                    resource_id(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_grants_none!(
+    Namespace,
+    ResourceQuota,
+    LimitRange,
+    NetworkPolicy,
+    ValidatingAdmissionPolicy,
+    ValidatingAdmissionPolicyBinding
+);
+
+impl_apply!(ClusterRole, cluster);
+impl_apply!(ClusterRoleBinding, cluster);
+impl_apply!(Namespace, cluster);
+impl_apply!(Role, namespaced);
+impl_apply!(RoleBinding, namespaced);
+impl_apply!(ResourceQuota, namespaced);
+impl_apply!(LimitRange, namespaced);
+impl_apply!(NetworkPolicy, namespaced);
+impl_apply!(ValidatingAdmissionPolicy, cluster);
+impl_apply!(ValidatingAdmissionPolicyBinding, cluster);
+
+fn resource_name<K: Resource<DynamicType = ()>>(resource: &K) -> Result<&str> {
+    resource
+        .meta()
+        .name
+        .as_deref()
+        .ok_or(CoralGateError::MissingName(K::kind(&()).into()))
+}
+
+fn resource_id<K: Resource<DynamicType = ()>>(resource: &K) -> String {
+    let name = resource.meta().name.as_deref().unwrap_or_default();
+
+    match &resource.meta().namespace {
+        Some(namespace) => format!("{}/{} ({})", K::kind(&()), name, namespace),
+        None => format!("{}/{}", K::kind(&()), name),
+    }
+}
+
+/// Copy of the resource carrying the coralgate labels next to its own
+fn labeled<K: Resource + Clone>(resource: &K) -> K {
+    let mut resource = resource.clone();
+    let labels = resource.meta_mut().labels.get_or_insert_default();
+
+    for (key, value) in shared::generate_lables().unwrap_or_default() {
+        labels.entry(key).or_insert(value);
+    }
+
+    resource
+}
+
+#[derive(Debug, PartialEq)]
+enum Ownership {
+    Missing,
+    Owned,
+    /// Owned through the field manager of an older release only
+    Legacy,
+    Foreign,
+}
+
+fn ownership<K: Resource>(live: Option<&K>) -> Ownership {
+    let Some(live) = live else {
+        return Ownership::Missing;
+    };
+
+    let meta = live.meta();
+    let labeled = meta
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(shared::CREATED_BY_LABEL))
+        .is_some_and(|value| value == shared::CREATED_BY_VALUE);
+
+    let managers: Vec<&str> = meta
+        .managed_fields
+        .iter()
+        .flatten()
+        .filter_map(|entry| entry.manager.as_deref())
+        .collect();
+
+    if labeled || managers.contains(&shared::FIELD_MANAGER) {
+        Ownership::Owned
+    } else if managers
+        .iter()
+        .any(|manager| OWN_FIELD_MANAGERS.contains(manager))
+    {
+        Ownership::Legacy
+    } else {
+        Ownership::Foreign
+    }
+}
+
+/// Turns a server side apply conflict into something a person can act on
+fn conflict_error(resource: String, error: kube::Error) -> CoralGateError {
+    match error {
+        kube::Error::Api(status) if status.code == 409 => {
+            let conflicts = status
+                .details
+                .as_ref()
+                .map(|details| {
+                    details
+                        .causes
+                        .iter()
+                        .map(|cause| format!("{} ({})", cause.field, cause.message))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .filter(|conflicts| !conflicts.is_empty())
+                .unwrap_or(status.message);
+
+            CoralGateError::FieldManagerConflict {
+                resource,
+                conflicts,
+            }
+        }
+        error => error.into(),
+    }
+}
+
+/// Returns whether the object was created
+async fn apply_with<K>(api: &Api<K>, resource: &K, options: &ApplyOptions) -> Result<bool>
+where
+    K: Resource<DynamicType = ()> + Clone + Serialize + DeserializeOwned + Debug,
+{
+    let resource = labeled(resource);
+    let name = resource_name(&resource)?;
+
+    let ownership = ownership(api.get_opt(name).await?.as_ref());
+    let force = match ownership {
+        Ownership::Foreign if !options.adopt => {
+            return Err(CoralGateError::NotOwned(resource_id(&resource)));
+        }
+        Ownership::Missing | Ownership::Owned => options.adopt,
+        Ownership::Legacy | Ownership::Foreign => true,
+    };
+
+    let mut params = PatchParams::apply(shared::FIELD_MANAGER);
+    params.force = force;
+
+    api.patch(name, &params, &Patch::Apply(&resource))
+        .await
+        .map_err(|error| conflict_error(resource_id(&resource), error))?;
+    tracing::debug!(resource = %resource_id(&resource), ?ownership, force, "applied");
+
+    Ok(ownership == Ownership::Missing)
+}
+
+async fn delete_with<K>(api: &Api<K>, resource: &K, options: &ApplyOptions) -> Result<()>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    let name = resource_name(resource)?;
+
+    if ownership(api.get_opt(name).await?.as_ref()) == Ownership::Foreign && !options.adopt {
+        return Err(CoralGateError::NotOwned(resource_id(resource)));
+    }
+
+    match api
+        .delete(resource_name(resource)?, &DeleteParams::default())
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(status)) if status.is_not_found() => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+async fn diff_with<K>(api: &Api<K>, resource: &K) -> Result<ResourceDiff>
+where
+    K: Resource<DynamicType = ()> + Clone + Serialize + DeserializeOwned + Debug,
+{
+    let resource = labeled(resource);
+    let name = resource_name(&resource)?;
+
+    let Some(live) = api.get_opt(name).await? else {
+        return Ok(ResourceDiff {
+            resource: resource_id(&resource),
+            change: Change::Create,
+        });
+    };
+
+    if ownership(Some(&live)) == Ownership::Foreign {
+        return Ok(ResourceDiff {
+            resource: resource_id(&resource),
+            change: Change::NotOwned,
+        });
+    }
+
+    let desired = api
+        .patch(
             name,
-            &PatchParams::apply("kaccess").force(),
-            &Patch::Apply(self),
+            &PatchParams::apply(shared::FIELD_MANAGER).force().dry_run(),
+            &Patch::Apply(&resource),
         )
         .await?;
 
-        Ok(())
+    let mut changes = Vec::new();
+    compare_values(
+        "",
+        &comparable(serde_json::to_value(&live).unwrap_or_default()),
+        &comparable(serde_json::to_value(&desired).unwrap_or_default()),
+        &mut changes,
+    );
+
+    let change = if changes.is_empty() {
+        Change::Unchanged
+    } else {
+        Change::Update(changes)
+    };
+
+    Ok(ResourceDiff {
+        resource: resource_id(&resource),
+        change,
+    })
+}
+
+/// Strips the fields the api server maintains on its own
+fn comparable(mut value: Value) -> Value {
+    if let Some(object) = value.as_object_mut() {
+        object.remove("status");
+
+        if let Some(Value::Object(metadata)) = object.get_mut("metadata") {
+            metadata.retain(|key, _| key == "labels" || key == "annotations");
+        }
+    }
+
+    value
+}
+
+fn compare_values(path: &str, live: &Value, desired: &Value, changes: &mut Vec<String>) {
+    match (live, desired) {
+        (Value::Object(live), Value::Object(desired)) => {
+            let mut keys: Vec<&String> = live.keys().chain(desired.keys()).collect();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+
+                compare_values(
+                    &child,
+                    live.get(key).unwrap_or(&Value::Null),
+                    desired.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (live, desired) if live != desired => {
+            changes.push(format!("{}: {} -> {}", path, live, desired));
+        }
+        _ => {}
     }
 }
 
@@ -83,6 +591,7 @@ pub fn admin_profile() -> Profile {
     let binding = ClusterRoleBinding {
         metadata: ObjectMeta {
             name: Some("coralgate-cluster-admin-binding".into()),
+            labels: shared::generate_profile_lables("admin"),
             ..Default::default()
         },
         subjects: Some(vec![Subject {
@@ -100,7 +609,15 @@ pub fn admin_profile() -> Profile {
 
     Profile {
         name: "admin".into(),
+        group: "cluster-admins".into(),
         resources: vec![Box::new(binding)],
+        checks: vec![
+            AccessCheck::allow("delete", "pods").in_namespace("default"),
+            AccessCheck::allow("create", "clusterrolebindings")
+                .in_api_group("rbac.authorization.k8s.io"),
+        ],
+        // cluster-admin is everything on purpose
+        acknowledged: lint::all_findings(),
     }
 }
 
@@ -108,6 +625,7 @@ pub fn cluster_readonly_profile() -> Profile {
     let binding = ClusterRoleBinding {
         metadata: ObjectMeta {
             name: Some("coralgate-cluster-readonly-binding".into()),
+            labels: shared::generate_profile_lables("cluster-readonly"),
             ..Default::default()
         },
         subjects: Some(vec![Subject {
@@ -125,32 +643,307 @@ pub fn cluster_readonly_profile() -> Profile {
 
     Profile {
         name: "cluster-readonly".into(),
+        group: "cluster-readonly".into(),
         resources: vec![Box::new(binding)],
+        acknowledged: vec![],
+        checks: vec![
+            AccessCheck::allow("list", "pods"),
+            AccessCheck::allow("get", "deployments").in_api_group("apps"),
+            AccessCheck::deny("delete", "pods").in_namespace("default"),
+            AccessCheck::deny("create", "pods").in_namespace("default"),
+            AccessCheck::deny("get", "secrets").in_namespace("default"),
+        ],
     }
 }
 
 pub fn namespaced_readonly(namespace: &str) -> Profile {
+    let mut profile = namespaced("readonly", "namespaced-readonly", "view", namespace);
+    profile.checks = vec![
+        AccessCheck::allow("list", "pods").in_namespace(namespace),
+        AccessCheck::deny("delete", "pods").in_namespace(namespace),
+        AccessCheck::deny("get", "secrets").in_namespace(namespace),
+        AccessCheck::deny("list", "pods"),
+    ];
+
+    profile
+}
+
+/// Day to day write access to one namespace through the built-in `edit` role,
+/// which excludes RBAC and the namespace itself
+pub fn namespaced_edit(namespace: &str) -> Profile {
+    let mut profile = namespaced("edit", "namespaced-edit", "edit", namespace);
+    profile.checks = vec![
+        AccessCheck::allow("delete", "pods").in_namespace(namespace),
+        AccessCheck::allow("update", "deployments")
+            .in_api_group("apps")
+            .in_namespace(namespace),
+        AccessCheck::deny("create", "rolebindings")
+            .in_api_group("rbac.authorization.k8s.io")
+            .in_namespace(namespace),
+        AccessCheck::deny("delete", "pods"),
+    ];
+    profile.acknowledged = [
+        "secrets-read",
+        "pods-exec",
+        "serviceaccount-token",
+        "workload-create",
+    ]
+    .map(String::from)
+    .to_vec();
+
+    profile
+}
+
+/// Full control of one namespace through the built-in `admin` role, including
+/// its RoleBindings but not the namespace itself or its quota
+pub fn namespaced_admin(namespace: &str) -> Profile {
+    let mut profile = namespaced("admin", "namespaced-admin", "admin", namespace);
+    profile.checks = vec![
+        AccessCheck::allow("create", "rolebindings")
+            .in_api_group("rbac.authorization.k8s.io")
+            .in_namespace(namespace),
+        AccessCheck::allow("delete", "deployments")
+            .in_api_group("apps")
+            .in_namespace(namespace),
+        AccessCheck::deny("delete", "namespaces"),
+        AccessCheck::deny("delete", "pods"),
+    ];
+    profile.acknowledged = [
+        "secrets-read",
+        "pods-exec",
+        "serviceaccount-token",
+        "workload-create",
+        "rbac-write",
+    ]
+    .map(String::from)
+    .to_vec();
+
+    profile
+}
+
+/// A RoleBinding in the namespace to a built-in ClusterRole, for the
+/// `<prefix>-<namespace>` group
+fn namespaced(prefix: &str, label: &str, cluster_role: &str, namespace: &str) -> Profile {
+    let name = format!("{}-{}", prefix, namespace);
     let binding = RoleBinding {
         metadata: ObjectMeta {
-            name: Some(format!("readonly-{}", namespace)),
+            name: Some(name.clone()),
             namespace: Some(namespace.into()),
+            labels: shared::generate_profile_lables(label),
             ..Default::default()
         },
         subjects: Some(vec![Subject {
             kind: "Group".into(),
-            name: format!("readonly-{}", namespace),
+            name: name.clone(),
             api_group: Some("rbac.authorization.k8s.io".into()),
             namespace: None,
         }]),
         role_ref: RoleRef {
             kind: "ClusterRole".into(),
-            name: "view".into(),
+            name: cluster_role.into(),
             api_group: "rbac.authorization.k8s.io".into(),
         },
     };
 
     Profile {
-        name: format!("readonly-{}", namespace),
+        name: name.clone(),
+        group: name,
         resources: vec![Box::new(binding)],
+        acknowledged: vec![],
+        checks: vec![],
+    }
+}
+
+/// A profile described in YAML, as written by `profile synthesize`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileDefinition {
+    pub name: String,
+    /// Group the issued certificates carry, defaults to the profile name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Rules granted cluster wide through a ClusterRole
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cluster_rules: Vec<PolicyRule>,
+    /// Rules granted per namespace through a Role
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespaces: BTreeMap<String, Vec<PolicyRule>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<AccessCheck>,
+    /// Lint findings granted on purpose
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub acknowledged: Vec<String>,
+}
+
+impl ProfileDefinition {
+    pub async fn read(path: &str) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path).await?;
+
+        serde_yaml::from_str(&content).map_err(|error| CoralGateError::InvalidProfile {
+            path: path.into(),
+            reason: error.to_string(),
+        })
+    }
+
+    /// Ships the rules as coralgate-<name> roles bound to the profile group
+    pub fn into_profile(self) -> Profile {
+        let group = self.group.clone().unwrap_or(self.name.clone());
+        let role_name = format!("coralgate-{}", self.name);
+        let labels = shared::generate_profile_lables(&self.name);
+        let subjects = Some(vec![Subject {
+            kind: "Group".into(),
+            name: group.clone(),
+            api_group: Some("rbac.authorization.k8s.io".into()),
+            namespace: None,
+        }]);
+
+        let mut resources: Vec<Box<dyn Apply + Send + Sync>> = Vec::new();
+
+        if !self.cluster_rules.is_empty() {
+            resources.push(Box::new(ClusterRole {
+                metadata: ObjectMeta {
+                    name: Some(role_name.clone()),
+                    labels: labels.clone(),
+                    ..Default::default()
+                },
+                rules: Some(self.cluster_rules),
+                ..Default::default()
+            }));
+            resources.push(Box::new(ClusterRoleBinding {
+                metadata: ObjectMeta {
+                    name: Some(format!("{}-binding", role_name)),
+                    labels: labels.clone(),
+                    ..Default::default()
+                },
+                subjects: subjects.clone(),
+                role_ref: RoleRef {
+                    kind: "ClusterRole".into(),
+                    name: role_name.clone(),
+                    api_group: "rbac.authorization.k8s.io".into(),
+                },
+            }));
+        }
+
+        for (namespace, rules) in self.namespaces {
+            resources.push(Box::new(Role {
+                metadata: ObjectMeta {
+                    name: Some(role_name.clone()),
+                    namespace: Some(namespace.clone()),
+                    labels: labels.clone(),
+                    ..Default::default()
+                },
+                rules: Some(rules),
+            }));
+            resources.push(Box::new(RoleBinding {
+                metadata: ObjectMeta {
+                    name: Some(format!("{}-binding", role_name)),
+                    namespace: Some(namespace),
+                    labels: labels.clone(),
+                    ..Default::default()
+                },
+                subjects: subjects.clone(),
+                role_ref: RoleRef {
+                    kind: "Role".into(),
+                    name: role_name.clone(),
+                    api_group: "rbac.authorization.k8s.io".into(),
+                },
+            }));
+        }
+
+        Profile {
+            name: self.name,
+            group,
+            resources,
+            checks: self.checks,
+            acknowledged: self.acknowledged,
+        }
+    }
+}
+
+/// Looks a built-in cluster wide profile up by name
+pub fn builtin(name: &str) -> Option<Profile> {
+    [admin_profile(), cluster_readonly_profile()]
+        .into_iter()
+        .find(|profile| profile.name() == name)
+}
+
+fn namespaced_by_name(name: &str) -> Option<fn(&str) -> Profile> {
+    match name {
+        "namespaced-readonly" => Some(namespaced_readonly),
+        "namespaced-edit" => Some(namespaced_edit),
+        "namespaced-admin" => Some(namespaced_admin),
+        _ => None,
+    }
+}
+
+/// Whether the name is a built-in namespaced profile. `setup` does not install
+/// those, so nothing binds their group and they are only bound per user
+pub fn is_namespaced(name: &str) -> bool {
+    namespaced_by_name(name).is_some()
+}
+
+/// Any built-in profile by name, namespaced ones need the namespace
+pub fn resolve(name: &str, namespace: Option<&str>) -> Result<Profile> {
+    if let Some(profile) = builtin(name) {
+        return Ok(profile);
+    }
+
+    let namespaced = namespaced_by_name(name).ok_or(CoralGateError::UnknownProfile(name.into()))?;
+
+    namespace
+        .map(namespaced)
+        .ok_or(CoralGateError::MissingNamespace(name.into()))
+}
+
+/// The built-in profile behind a `--profile` value
+pub fn from_permission(permission: &PermissionProfile) -> Profile {
+    match permission {
+        PermissionProfile::Admin => admin_profile(),
+        PermissionProfile::ClusterReadonly => cluster_readonly_profile(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding_metadata(subject: SubjectBinding) -> ObjectMeta {
+        let profile = ObjectMeta {
+            name: Some("edit-prod".into()),
+            namespace: Some("prod".into()),
+            labels: shared::generate_profile_lables("namespaced-edit"),
+            ..Default::default()
+        };
+
+        subject.metadata(&profile)
+    }
+
+    #[test]
+    fn colliding_names_get_their_own_bindings() {
+        for (first, second) in [("bob.smith", "bob-smith"), ("Alice", "alice")] {
+            let first = binding_metadata(SubjectBinding::user(first));
+            let second = binding_metadata(SubjectBinding::user(second));
+
+            assert_ne!(first.name, second.name);
+            assert_ne!(
+                first.labels.unwrap()[shared::USER_LABEL],
+                second.labels.unwrap()[shared::USER_LABEL]
+            );
+        }
+
+        let dev = binding_metadata(SubjectBinding::group("Dev"));
+        assert_ne!(
+            dev.name,
+            binding_metadata(SubjectBinding::group("dev")).name
+        );
+    }
+
+    #[test]
+    fn user_bindings_are_named_and_labelled_alike() {
+        let metadata = binding_metadata(SubjectBinding::user("bob.smith"));
+        let label = shared::unique_label("bob.smith");
+
+        assert_eq!(metadata.name, Some(format!("edit-prod-{}", label)));
+        assert_eq!(metadata.labels.unwrap()[shared::USER_LABEL], label);
     }
 }
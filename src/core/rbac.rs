@@ -0,0 +1,356 @@
+use crate::error::*;
+use crate::shared;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s_openapi::api::rbac::v1::{
+    ClusterRole, ClusterRoleBinding, PolicyRule, Role, RoleBinding, RoleRef, Subject,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::api::ListParams;
+use kube::{Api, Client, ResourceExt};
+use serde_json::Value;
+
+/// Every authenticated request carries this group, whatever the certificate says
+pub const AUTHENTICATED_GROUP: &str = "system:authenticated";
+
+/// The RBAC objects of a cluster, fetched live or read from a dump
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub cluster_roles: Vec<ClusterRole>,
+    pub roles: Vec<Role>,
+    pub cluster_role_bindings: Vec<ClusterRoleBinding>,
+    pub role_bindings: Vec<RoleBinding>,
+}
+
+/// A rule someone holds and the binding that grants it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EffectiveRule {
+    /// Cluster wide when empty
+    pub namespace: Option<String>,
+    pub rule: PolicyRule,
+    /// `Kind/name` of the binding
+    pub binding: String,
+    /// `Kind/name` of the role the binding references
+    pub role: String,
+}
+
+/// A binding together with where it applies
+#[derive(Debug, Clone)]
+pub struct BindingRef<'a> {
+    pub kind: &'static str,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub subjects: &'a [Subject],
+    pub role_ref: &'a RoleRef,
+    /// Carries the coralgate label
+    pub managed: bool,
+    /// Coralgate profile the binding belongs to
+    pub profile: Option<String>,
+}
+
+impl BindingRef<'_> {
+    pub fn id(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}/{}/{}", self.kind, namespace, self.name),
+            None => format!("{}/{}", self.kind, self.name),
+        }
+    }
+
+    /// Whether the user, or one of the groups, is among the subjects
+    pub fn matches(&self, user: &str, groups: &[String]) -> bool {
+        self.subjects
+            .iter()
+            .any(|subject| match subject.kind.as_str() {
+                "User" => subject.name == user,
+                "Group" => groups.contains(&subject.name),
+                _ => false,
+            })
+    }
+}
+
+impl Snapshot {
+    pub async fn fetch(client: &Client) -> Result<Self> {
+        let params = ListParams::default();
+
+        Ok(Self {
+            cluster_roles: Api::<ClusterRole>::all(client.clone())
+                .list(&params)
+                .await?
+                .items,
+            roles: Api::<Role>::all(client.clone()).list(&params).await?.items,
+            cluster_role_bindings: Api::<ClusterRoleBinding>::all(client.clone())
+                .list(&params)
+                .await?
+                .items,
+            role_bindings: Api::<RoleBinding>::all(client.clone())
+                .list(&params)
+                .await?
+                .items,
+        })
+    }
+
+    /// Reads a dump as produced by
+    /// `kubectl get clusterroles,roles,clusterrolebindings,rolebindings -A -o yaml`
+    /// or by [`Snapshot::write`], objects of other kinds are skipped
+    pub async fn read(path: &str) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path).await?;
+        let invalid = |reason: String| CoralGateError::InvalidSnapshot {
+            path: path.into(),
+            reason,
+        };
+
+        let mut snapshot = Self::default();
+        for document in serde_yaml::Deserializer::from_str(&content) {
+            let value: Value = serde::Deserialize::deserialize(document)
+                .map_err(|error| invalid(error.to_string()))?;
+
+            let items = match value.get("kind").and_then(Value::as_str) {
+                Some("List") => value
+                    .get("items")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => vec![value],
+            };
+
+            for item in items {
+                snapshot
+                    .push(item)
+                    .map_err(|error| invalid(error.to_string()))?;
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    fn push(&mut self, item: Value) -> serde_json::Result<()> {
+        match item.get("kind").and_then(Value::as_str) {
+            Some("ClusterRole") => self.cluster_roles.push(serde_json::from_value(item)?),
+            Some("Role") => self.roles.push(serde_json::from_value(item)?),
+            Some("ClusterRoleBinding") => self
+                .cluster_role_bindings
+                .push(serde_json::from_value(item)?),
+            Some("RoleBinding") => self.role_bindings.push(serde_json::from_value(item)?),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Dumps the snapshot as a YAML list, readable by [`Snapshot::read`]
+    pub async fn write(&self, path: &str) -> Result<()> {
+        let mut items = Vec::new();
+        items.extend(self.cluster_roles.iter().map(serde_json::to_value));
+        items.extend(self.roles.iter().map(serde_json::to_value));
+        items.extend(self.cluster_role_bindings.iter().map(serde_json::to_value));
+        items.extend(self.role_bindings.iter().map(serde_json::to_value));
+
+        let items: Vec<Value> = items.into_iter().filter_map(|item| item.ok()).collect();
+        let list = serde_json::json!({ "apiVersion": "v1", "kind": "List", "items": items });
+        let content = serde_yaml::to_string(&list).unwrap_or_default();
+
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+
+    pub fn bindings(&self) -> Vec<BindingRef<'_>> {
+        let cluster = self.cluster_role_bindings.iter().map(|binding| BindingRef {
+            kind: "ClusterRoleBinding",
+            name: binding.name_any(),
+            namespace: None,
+            subjects: binding.subjects.as_deref().unwrap_or_default(),
+            role_ref: &binding.role_ref,
+            managed: is_managed(binding),
+            profile: binding.labels().get(shared::PROFILE_LABEL).cloned(),
+        });
+
+        let namespaced = self.role_bindings.iter().map(|binding| BindingRef {
+            kind: "RoleBinding",
+            name: binding.name_any(),
+            namespace: binding.namespace(),
+            subjects: binding.subjects.as_deref().unwrap_or_default(),
+            role_ref: &binding.role_ref,
+            managed: is_managed(binding),
+            profile: binding.labels().get(shared::PROFILE_LABEL).cloned(),
+        });
+
+        cluster.chain(namespaced).collect()
+    }
+
+    /// Rules of a ClusterRole, with aggregated ClusterRoles expanded
+    pub fn cluster_role_rules(&self, name: &str) -> Vec<PolicyRule> {
+        let mut visited = BTreeSet::new();
+        self.collect_cluster_role_rules(name, &mut visited)
+    }
+
+    fn collect_cluster_role_rules(
+        &self,
+        name: &str,
+        visited: &mut BTreeSet<String>,
+    ) -> Vec<PolicyRule> {
+        if !visited.insert(name.to_string()) {
+            return vec![];
+        }
+
+        let Some(role) = self
+            .cluster_roles
+            .iter()
+            .find(|role| role.name_any() == name)
+        else {
+            return vec![];
+        };
+
+        let selectors = role
+            .aggregation_rule
+            .as_ref()
+            .and_then(|rule| rule.cluster_role_selectors.as_ref());
+
+        let Some(selectors) = selectors else {
+            return role.rules.clone().unwrap_or_default();
+        };
+
+        // The api server fills in the rules of aggregated roles, dumps may not
+        let mut rules = role.rules.clone().unwrap_or_default();
+        for aggregated in &self.cluster_roles {
+            if selectors
+                .iter()
+                .any(|selector| selector_matches(selector, aggregated.labels()))
+            {
+                for rule in self.collect_cluster_role_rules(&aggregated.name_any(), visited) {
+                    if !rules.contains(&rule) {
+                        rules.push(rule);
+                    }
+                }
+            }
+        }
+
+        rules
+    }
+
+    /// Rules a binding grants, resolved through its role reference
+    pub fn binding_rules(&self, binding: &BindingRef) -> Vec<PolicyRule> {
+        match binding.role_ref.kind.as_str() {
+            "ClusterRole" => self.cluster_role_rules(&binding.role_ref.name),
+            "Role" => self
+                .roles
+                .iter()
+                .find(|role| {
+                    role.name_any() == binding.role_ref.name
+                        && role.namespace() == binding.namespace
+                })
+                .and_then(|role| role.rules.clone())
+                .unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    /// Everything the user, as member of the groups, is allowed to do
+    pub fn effective_rules(&self, user: &str, groups: &[String]) -> Vec<EffectiveRule> {
+        let mut effective = Vec::new();
+
+        for binding in self.bindings() {
+            if !binding.matches(user, groups) {
+                continue;
+            }
+
+            for rule in self.binding_rules(&binding) {
+                effective.push(EffectiveRule {
+                    namespace: binding.namespace.clone(),
+                    rule,
+                    binding: binding.id(),
+                    role: format!("{}/{}", binding.role_ref.kind, binding.role_ref.name),
+                });
+            }
+        }
+
+        effective
+    }
+}
+
+/// Effective rules keyed by namespace, cluster wide rules under `None` come first
+pub fn by_namespace(rules: Vec<EffectiveRule>) -> BTreeMap<Option<String>, Vec<EffectiveRule>> {
+    let mut grouped: BTreeMap<Option<String>, Vec<EffectiveRule>> = BTreeMap::new();
+
+    for rule in rules {
+        grouped
+            .entry(rule.namespace.clone())
+            .or_default()
+            .push(rule);
+    }
+
+    grouped
+}
+
+fn is_managed<K: ResourceExt>(object: &K) -> bool {
+    object
+        .labels()
+        .get(shared::CREATED_BY_LABEL)
+        .is_some_and(|value| value == shared::CREATED_BY_VALUE)
+}
+
+pub fn selector_matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let labels_match = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+
+    let expressions_match = selector
+        .match_expressions
+        .iter()
+        .flatten()
+        .all(|expression| {
+            let values = expression.values.as_deref().unwrap_or_default();
+            let value = labels.get(&expression.key);
+
+            match expression.operator.as_str() {
+                "In" => value.is_some_and(|value| values.contains(value)),
+                "NotIn" => value.is_none_or(|value| !values.contains(value)),
+                "Exists" => value.is_some(),
+                "DoesNotExist" => value.is_none(),
+                _ => false,
+            }
+        });
+
+    labels_match && expressions_match
+}
+
+/// One line summary of a rule, like `get,list pods,services (apps)`
+pub fn describe_rule(rule: &PolicyRule) -> String {
+    let verbs = rule.verbs.join(",");
+
+    if let Some(urls) = rule
+        .non_resource_urls
+        .as_ref()
+        .filter(|urls| !urls.is_empty())
+    {
+        return format!("{} {}", verbs, urls.join(","));
+    }
+
+    let mut description = format!(
+        "{} {}",
+        verbs,
+        rule.resources.as_deref().unwrap_or_default().join(",")
+    );
+
+    let api_groups: Vec<&str> = rule
+        .api_groups
+        .iter()
+        .flatten()
+        .map(|group| if group.is_empty() { "core" } else { group })
+        .collect();
+    if !api_groups.is_empty() {
+        description.push_str(&format!(" ({})", api_groups.join(",")));
+    }
+
+    if let Some(names) = rule
+        .resource_names
+        .as_ref()
+        .filter(|names| !names.is_empty())
+    {
+        description.push_str(&format!(" named {}", names.join(",")));
+    }
+
+    description
+}
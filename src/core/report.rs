@@ -0,0 +1,236 @@
+use crate::core::ledger::Grant;
+use crate::core::rbac::{BindingRef, Snapshot};
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Who has access through what, one entry per user grant or bound subject
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessEntry {
+    /// User, Group or ServiceAccount
+    pub kind: String,
+    pub subject: String,
+    pub profile: String,
+    /// Namespaces the access applies to, `*` for cluster wide
+    pub namespaces: Vec<String>,
+    /// `binding -> role` pairs granting the access
+    pub granted_by: Vec<String>,
+    pub issued_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub reason: Option<String>,
+    /// Issued or bound by coralgate
+    pub coralgate: bool,
+    /// Emergency access issued with `break-glass`
+    pub break_glass: bool,
+}
+
+impl AccessEntry {
+    fn add_binding(&mut self, binding: &BindingRef) {
+        let namespace = binding.namespace.clone().unwrap_or("*".into());
+        if !self.namespaces.contains(&namespace) {
+            self.namespaces.push(namespace);
+        }
+
+        self.granted_by.push(format!(
+            "{} -> {}/{}",
+            binding.id(),
+            binding.role_ref.kind,
+            binding.role_ref.name
+        ));
+    }
+}
+
+/// Combines the active grants with the bindings of the cluster, subjects
+/// bound outside coralgate are only listed when `include_external` is set
+pub fn access(snapshot: &Snapshot, grants: &[Grant], include_external: bool) -> Vec<AccessEntry> {
+    let bindings = snapshot.bindings();
+    let mut entries: Vec<AccessEntry> = Vec::new();
+
+    for grant in grants.iter().filter(|grant| grant.is_active()) {
+        let mut entry = AccessEntry {
+            kind: "User".into(),
+            subject: grant.user.clone(),
+            profile: grant.profile.clone(),
+            namespaces: vec![],
+            granted_by: vec![],
+            issued_at: Some(grant.issued_at),
+            expires_at: Some(grant.expires_at),
+            reason: grant.reason.clone(),
+            coralgate: true,
+            break_glass: grant.break_glass,
+        };
+
+        for binding in bindings
+            .iter()
+            .filter(|binding| binding.matches(&grant.user, &grant.groups))
+        {
+            entry.add_binding(binding);
+        }
+
+        entries.push(entry);
+    }
+
+    // Position in `entries` of each bound subject, by kind and name
+    let mut bound: BTreeMap<(String, String), usize> = BTreeMap::new();
+    for binding in &bindings {
+        if !binding.managed && !include_external {
+            continue;
+        }
+
+        for subject in binding.subjects {
+            let name = match (subject.kind.as_str(), &subject.namespace) {
+                ("ServiceAccount", Some(namespace)) => format!("{}/{}", namespace, subject.name),
+                _ => subject.name.clone(),
+            };
+
+            // Users holding a grant are already listed with their issuance
+            if subject.kind == "User"
+                && grants
+                    .iter()
+                    .any(|grant| grant.is_active() && grant.user == name)
+            {
+                continue;
+            }
+
+            let key = (subject.kind.clone(), name.clone());
+            let index = *bound.entry(key).or_insert_with(|| {
+                entries.push(AccessEntry {
+                    kind: subject.kind.clone(),
+                    subject: name,
+                    profile: String::new(),
+                    namespaces: vec![],
+                    granted_by: vec![],
+                    issued_at: None,
+                    expires_at: None,
+                    reason: None,
+                    coralgate: false,
+                    break_glass: false,
+                });
+                entries.len() - 1
+            });
+            let entry = &mut entries[index];
+
+            if let Some(profile) = &binding.profile {
+                entry.profile = profile.clone();
+            }
+            entry.coralgate |= binding.managed;
+            entry.add_binding(binding);
+        }
+    }
+
+    entries
+}
+
+fn format_time(time: &Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339()).unwrap_or_default()
+}
+
+pub fn render_markdown(entries: &[AccessEntry]) -> String {
+    let mut report = String::from(
+        "| Kind | Subject | Profile | Namespaces | Granted by | Issued at | Expires at | Reason | Coralgate | Break glass |\n\
+         |---|---|---|---|---|---|---|---|---|---|\n",
+    );
+
+    for entry in entries {
+        report.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} | {} |\n",
+            markdown_escape(&entry.kind),
+            markdown_escape(&entry.subject),
+            markdown_escape(&entry.profile),
+            markdown_escape(&entry.namespaces.join(", ")),
+            entry
+                .granted_by
+                .iter()
+                .map(|granted_by| markdown_escape(granted_by))
+                .collect::<Vec<_>>()
+                .join("<br>"),
+            format_time(&entry.issued_at),
+            format_time(&entry.expires_at),
+            markdown_escape(entry.reason.as_deref().unwrap_or_default()),
+            if entry.coralgate { "yes" } else { "no" },
+            if entry.break_glass { "**yes**" } else { "no" }
+        ));
+    }
+
+    report
+}
+
+/// Keeps a value inside its table cell
+fn markdown_escape(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+pub fn render_csv(entries: &[AccessEntry]) -> String {
+    let mut report = String::from(
+        "kind,subject,profile,namespaces,granted_by,issued_at,expires_at,reason,coralgate,break_glass\n",
+    );
+
+    for entry in entries {
+        let fields = [
+            entry.kind.clone(),
+            entry.subject.clone(),
+            entry.profile.clone(),
+            entry.namespaces.join(";"),
+            entry.granted_by.join(";"),
+            format_time(&entry.issued_at),
+            format_time(&entry.expires_at),
+            entry.reason.clone().unwrap_or_default(),
+            entry.coralgate.to_string(),
+            entry.break_glass.to_string(),
+        ];
+
+        let fields: Vec<String> = fields.iter().map(|field| csv_escape(field)).collect();
+        report.push_str(&fields.join(","));
+        report.push('\n');
+    }
+
+    report
+}
+
+pub fn render_json(entries: &[AccessEntry]) -> String {
+    serde_json::to_string_pretty(entries).unwrap_or_default()
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markdown_cells() {
+        let entry = AccessEntry {
+            kind: "User".into(),
+            subject: "alice|bob".into(),
+            profile: "admin".into(),
+            namespaces: vec!["*".into()],
+            granted_by: vec!["ClusterRoleBinding/a|b -> ClusterRole/admin".into()],
+            issued_at: None,
+            expires_at: None,
+            reason: Some("INC-1 | db\ndown".into()),
+            coralgate: true,
+            break_glass: false,
+        };
+
+        let row = render_markdown(&[entry])
+            .lines()
+            .nth(2)
+            .unwrap()
+            .to_string();
+
+        assert!(row.contains("| alice\\|bob |"), "{}", row);
+        assert!(row.contains("ClusterRoleBinding/a\\|b"), "{}", row);
+        assert!(row.contains("| INC-1 \\| db down |"), "{}", row);
+        let cells = row.replace("\\|", "").matches('|').count() - 1;
+        assert_eq!(cells, 10, "{}", row);
+    }
+}
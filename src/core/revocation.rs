@@ -0,0 +1,257 @@
+use crate::core::profile::{Apply, ApplyOptions};
+use crate::error::*;
+use crate::shared;
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::admissionregistration::v1::{
+    MatchResources, NamedRuleWithOperations, ParamKind, ParamRef, ValidatingAdmissionPolicy,
+    ValidatingAdmissionPolicyBinding, ValidatingAdmissionPolicyBindingSpec,
+    ValidatingAdmissionPolicySpec, Validation,
+};
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::PostParams;
+use kube::{Api, Client};
+use serde::Serialize;
+
+/// Name of the policy, its binding and the ConfigMap listing revoked users
+const REVOCATIONS: &str = "coralgate-revocations";
+
+/// Data keys of the denylist, `user.<key>` holds the username the policy
+/// matches, `expires.<key>` when the entry can be dropped. See [`key`]
+const USER_PREFIX: &str = "user.";
+const EXPIRES_PREFIX: &str = "expires.";
+
+/// A revoked identity
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revocation {
+    pub user: String,
+    /// Expiry of the last certificate issued to the user, kept forever when unknown
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn policy() -> ValidatingAdmissionPolicy {
+    ValidatingAdmissionPolicy {
+        metadata: ObjectMeta {
+            name: Some(REVOCATIONS.into()),
+            labels: shared::generate_lables(),
+            ..Default::default()
+        },
+        spec: Some(ValidatingAdmissionPolicySpec {
+            failure_policy: Some("Fail".into()),
+            param_kind: Some(ParamKind {
+                api_version: Some("v1".into()),
+                kind: Some("ConfigMap".into()),
+            }),
+            match_constraints: Some(MatchResources {
+                resource_rules: Some(vec![NamedRuleWithOperations {
+                    api_groups: Some(vec!["*".into()]),
+                    api_versions: Some(vec!["*".into()]),
+                    resources: Some(vec!["*".into()]),
+                    operations: Some(vec![
+                        "CREATE".into(),
+                        "UPDATE".into(),
+                        "DELETE".into(),
+                        "CONNECT".into(),
+                    ]),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            validations: Some(vec![Validation {
+                expression: format!(
+                    "!has(params.data) || !params.data.exists(k, k.startsWith('{}') && params.data[k] == request.userInfo.username)",
+                    USER_PREFIX
+                ),
+                message: Some("the credentials of this user were revoked".into()),
+                reason: Some("Forbidden".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        status: None,
+    }
+}
+
+fn binding() -> ValidatingAdmissionPolicyBinding {
+    ValidatingAdmissionPolicyBinding {
+        metadata: ObjectMeta {
+            name: Some(REVOCATIONS.into()),
+            labels: shared::generate_lables(),
+            ..Default::default()
+        },
+        spec: Some(ValidatingAdmissionPolicyBindingSpec {
+            policy_name: Some(REVOCATIONS.into()),
+            param_ref: Some(ParamRef {
+                name: Some(REVOCATIONS.into()),
+                namespace: Some(shared::NAMESPACE.into()),
+                parameter_not_found_action: Some("Allow".into()),
+                ..Default::default()
+            }),
+            validation_actions: Some(vec!["Deny".into()]),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Installs the policy rejecting mutating requests of revoked users
+pub async fn ensure_policy(client: &Client) -> Result<()> {
+    let options = ApplyOptions::default();
+
+    policy().apply(client, &options).await?;
+    binding().apply(client, &options).await
+}
+
+fn entries(data: &BTreeMap<String, String>) -> Vec<Revocation> {
+    data.iter()
+        .filter_map(|(key, user)| {
+            let key = key.strip_prefix(USER_PREFIX)?;
+            let expires_at = data
+                .get(&format!("{}{}", EXPIRES_PREFIX, key))
+                .and_then(|expires_at| expires_at.parse().ok());
+
+            Some(Revocation {
+                user: user.clone(),
+                expires_at,
+            })
+        })
+        .collect()
+}
+
+/// Drops entries whose certificates expired, the policy has nothing left to stop
+fn prune_expired(data: &mut BTreeMap<String, String>) -> Vec<String> {
+    let expired: Vec<String> = entries(data)
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .expires_at
+                .is_some_and(|expires_at| expires_at <= Utc::now())
+        })
+        .map(|entry| entry.user)
+        .collect();
+
+    for user in &expired {
+        remove(data, user);
+    }
+
+    expired
+}
+
+/// Hash of the exact username, distinct users never share an entry the way
+/// sanitized names like `Alice` and `alice` would
+fn key(user: &str) -> String {
+    shared::digest(user)
+}
+
+/// Matches on the stored username, so entries written under older keys go too
+fn remove(data: &mut BTreeMap<String, String>, user: &str) -> bool {
+    let keys: Vec<String> = data
+        .iter()
+        .filter(|(_, value)| value.as_str() == user)
+        .filter_map(|(key, _)| key.strip_prefix(USER_PREFIX))
+        .map(String::from)
+        .collect();
+
+    for key in &keys {
+        data.remove(&format!("{}{}", EXPIRES_PREFIX, key));
+        data.remove(&format!("{}{}", USER_PREFIX, key));
+    }
+
+    !keys.is_empty()
+}
+
+/// Reads the denylist, edits it and writes it back, pruning expired entries on the way
+async fn update<T>(
+    client: &Client,
+    edit: impl FnOnce(&mut BTreeMap<String, String>) -> T,
+) -> Result<(T, Vec<String>)> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+
+    let existing = api.get_opt(REVOCATIONS).await?;
+    let mut config_map = existing.clone().unwrap_or(ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(REVOCATIONS.into()),
+            namespace: Some(shared::NAMESPACE.into()),
+            labels: shared::generate_lables(),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let data = config_map.data.get_or_insert_default();
+    let pruned = prune_expired(data);
+    let result = edit(data);
+
+    // The resource version on the fetched object guards against concurrent edits
+    match existing {
+        Some(existing) if existing.data.as_ref() == Some(&*data) => {}
+        None if data.is_empty() => {}
+        Some(_) => {
+            api.replace(REVOCATIONS, &PostParams::default(), &config_map)
+                .await?;
+        }
+        None => {
+            api.create(&PostParams::default(), &config_map).await?;
+        }
+    }
+
+    Ok((result, pruned))
+}
+
+/// Adds the user to the denylist, returns users pruned because their certificates expired
+pub async fn revoke(client: &Client, revocation: &Revocation) -> Result<Vec<String>> {
+    let (_, pruned) = update(client, |data| {
+        remove(data, &revocation.user);
+        let key = key(&revocation.user);
+        data.insert(format!("{}{}", USER_PREFIX, key), revocation.user.clone());
+
+        if let Some(expires_at) = revocation.expires_at {
+            data.insert(
+                format!("{}{}", EXPIRES_PREFIX, key),
+                expires_at.to_rfc3339(),
+            );
+        }
+    })
+    .await?;
+
+    Ok(pruned)
+}
+
+/// Removes the user from the denylist, false when the user was not revoked
+pub async fn unrevoke(client: &Client, user: &str) -> Result<(bool, Vec<String>)> {
+    update(client, |data| remove(data, user)).await
+}
+
+/// Prunes expired entries without other changes, nothing happens when no one was ever revoked
+pub async fn prune(client: &Client) -> Result<Vec<String>> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+    if api.get_opt(REVOCATIONS).await?.is_none() {
+        return Ok(vec![]);
+    }
+
+    let (_, pruned) = update(client, |_| ()).await?;
+    Ok(pruned)
+}
+
+/// Everyone currently on the denylist
+pub async fn list(client: &Client) -> Result<Vec<Revocation>> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+
+    let data = api
+        .get_opt(REVOCATIONS)
+        .await?
+        .and_then(|config_map| config_map.data)
+        .unwrap_or_default();
+
+    Ok(entries(&data)
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .expires_at
+                .is_none_or(|expires_at| expires_at > Utc::now())
+        })
+        .collect())
+}
@@ -0,0 +1,118 @@
+use crate::core::profile::{Apply, ApplyOptions};
+use crate::core::{csr, ledger};
+use crate::error::*;
+
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use k8s_openapi::api::core::v1::Namespace;
+use kube::api::DeleteParams;
+use kube::{Api, Client};
+
+/// Something a command created, undone when the command fails or is cancelled
+pub enum Undo {
+    Csr(String),
+    /// A role, binding or other object created through [`Apply`]
+    Object(Arc<dyn Apply + Send + Sync>),
+    Namespace(String),
+    /// Id of a ledger grant
+    Grant(String),
+}
+
+impl fmt::Display for Undo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Undo::Csr(name) => write!(f, "CertificateSigningRequest/{}", name),
+            Undo::Object(object) => write!(f, "{}", object.id()),
+            Undo::Namespace(name) => write!(f, "Namespace/{}", name),
+            Undo::Grant(id) => write!(f, "ledger grant {}", id),
+        }
+    }
+}
+
+impl Undo {
+    async fn undo(&self, client: &Client) -> Result<()> {
+        match self {
+            Undo::Csr(name) => csr::delete(&Api::all(client.clone()), name).await,
+            Undo::Object(object) => object.delete(client, &ApplyOptions::default()).await,
+            Undo::Namespace(name) => {
+                let api: Api<Namespace> = Api::all(client.clone());
+                match api.delete(name, &DeleteParams::default()).await {
+                    Ok(_) => Ok(()),
+                    Err(kube::Error::Api(status)) if status.is_not_found() => Ok(()),
+                    Err(error) => Err(error.into()),
+                }
+            }
+            Undo::Grant(id) => ledger::delete(client, id).await,
+        }
+    }
+}
+
+/// Creations not committed yet, by the transaction that made them. Transaction
+/// 0 is the command itself
+static STACK: Mutex<Vec<(u64, Undo)>> = Mutex::new(Vec::new());
+static NEXT_TRANSACTION: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static TRANSACTION: u64;
+}
+
+fn current() -> u64 {
+    TRANSACTION
+        .try_with(|transaction| *transaction)
+        .unwrap_or(0)
+}
+
+/// Remembers a creation in the current transaction
+pub fn record(undo: Undo) {
+    let transaction = current();
+    if let Ok(mut stack) = STACK.lock() {
+        stack.push((transaction, undo));
+    }
+}
+
+/// Keeps everything the current transaction created so far
+pub fn commit() {
+    let transaction = current();
+    if let Ok(mut stack) = STACK.lock() {
+        stack.retain(|(owner, _)| *owner != transaction);
+    }
+}
+
+/// Runs the future in its own transaction, committed when it succeeds. Used
+/// for work that stands on its own, like one user of a roster
+pub async fn scope<T, F>(future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let transaction = NEXT_TRANSACTION.fetch_add(1, Ordering::Relaxed);
+    let result = TRANSACTION.scope(transaction, future).await;
+
+    if result.is_ok() {
+        TRANSACTION.sync_scope(transaction, commit);
+    }
+
+    result
+}
+
+/// Undoes every uncommitted creation, newest first. Returns what was removed
+/// and what could not be
+pub async fn unwind(client: &Client) -> (Vec<String>, Vec<(String, CoralGateError)>) {
+    let pending: Vec<(u64, Undo)> = match STACK.lock() {
+        Ok(mut stack) => stack.drain(..).collect(),
+        Err(_) => vec![],
+    };
+
+    let mut removed = Vec::new();
+    let mut failed = Vec::new();
+    for (_, undo) in pending.into_iter().rev() {
+        match undo.undo(client).await {
+            Ok(()) => removed.push(undo.to_string()),
+            Err(error) => failed.push((undo.to_string(), error)),
+        }
+    }
+
+    (removed, failed)
+}
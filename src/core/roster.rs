@@ -0,0 +1,233 @@
+use crate::command::structure::BindingMode;
+use crate::core::profile;
+use crate::error::*;
+use crate::shared;
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// One user to issue for, the CSV columns use the same names
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterEntry {
+    pub user: String,
+    /// Any built-in profile, namespaced ones need `namespace`
+    pub profile: String,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Validity like `720h` or `30d`, `--expire` applies when missing
+    #[serde(default)]
+    pub expire: Option<String>,
+    /// `--binding-mode` applies when missing
+    #[serde(default)]
+    pub binding_mode: Option<BindingMode>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// age recipient the kubeconfig is encrypted for
+    #[serde(default)]
+    pub recipient: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Roster {
+    users: Vec<RosterEntry>,
+}
+
+/// Reads a roster, `.csv` files need a header row, anything else is YAML
+/// with a `users` list. `binding_mode` applies to entries without one
+pub fn read(path: &str, binding_mode: BindingMode) -> Result<Vec<RosterEntry>> {
+    let invalid = |reason: String| CoralGateError::InvalidRoster {
+        path: path.into(),
+        reason,
+    };
+
+    let content = std::fs::read_to_string(path)?;
+    let entries = if path.to_lowercase().ends_with(".csv") {
+        from_csv(&content).map_err(invalid)?
+    } else {
+        serde_yaml::from_str::<Roster>(&content)
+            .map_err(|error| invalid(error.to_string()))?
+            .users
+    };
+
+    // One kubeconfig file and one CSR per user, files are named after the
+    // sanitized user so `Alice` and `alice` would share one
+    let mut files: BTreeMap<String, &str> = BTreeMap::new();
+    for entry in &entries {
+        if entry.user.is_empty() {
+            return Err(invalid("an entry has no user".into()));
+        }
+        let file = shared::sanitize_name(&entry.user);
+        if file.is_empty() {
+            return Err(invalid(format!(
+                "{} has no letters or digits to name its kubeconfig after",
+                entry.user
+            )));
+        }
+        match files.insert(file.clone(), &entry.user) {
+            Some(other) if other == entry.user => {
+                return Err(invalid(format!("{} is listed more than once", entry.user)));
+            }
+            Some(other) => {
+                return Err(invalid(format!(
+                    "{} and {} would both be written to {}.kubeconfig",
+                    other, entry.user, file
+                )));
+            }
+            None => {}
+        }
+        if profile::is_namespaced(&entry.profile)
+            && entry.binding_mode.unwrap_or(binding_mode) == BindingMode::Group
+        {
+            return Err(invalid(format!(
+                "{}: {} is not bound to a group, use bindingMode: user",
+                entry.user, entry.profile
+            )));
+        }
+        if let Some(expire) = &entry.expire {
+            shared::parse_duration(expire)
+                .map_err(CoralGateError::InvalidDuration)
+                .and_then(shared::expiration_seconds)
+                .map_err(|error| invalid(format!("{}: {}", entry.user, error)))?;
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Plain comma separated values, no quoting, empty cells count as missing
+fn from_csv(content: &str) -> std::result::Result<Vec<RosterEntry>, String> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let header: Vec<String> = lines
+        .next()
+        .ok_or("the header row is missing")?
+        .split(',')
+        .map(|column| column.trim().to_string())
+        .collect();
+
+    lines
+        .enumerate()
+        .map(|(row, line)| {
+            let record: serde_json::Map<String, serde_json::Value> = header
+                .iter()
+                .zip(line.split(','))
+                .map(|(column, value)| (column.clone(), value.trim()))
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(column, value)| (column, value.into()))
+                .collect();
+
+            serde_json::from_value(record.into())
+                .map_err(|error| format!("row {}: {}", row + 1, error))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the roster where `read` can find it, removed again after reading
+    fn read_roster(name: &str, content: &str) -> Result<Vec<RosterEntry>> {
+        let path = std::env::temp_dir().join(format!("coralgate-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+
+        let entries = read(path.to_str().unwrap(), BindingMode::Group);
+        std::fs::remove_file(path).unwrap();
+
+        entries
+    }
+
+    #[test]
+    fn reads_csv_with_optional_columns() {
+        let entries = from_csv(
+            "# on-call team\n\
+             user, profile, namespace, expire, bindingMode\n\
+             alice, admin, , 8h,\n\
+             \n\
+             bob, namespaced-edit, prod, , user\n",
+        )
+        .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].user, "alice");
+        assert_eq!(entries[0].namespace, None);
+        assert_eq!(entries[0].expire.as_deref(), Some("8h"));
+        assert_eq!(entries[0].binding_mode, None);
+        assert_eq!(entries[1].namespace.as_deref(), Some("prod"));
+        assert_eq!(entries[1].binding_mode, Some(BindingMode::User));
+    }
+
+    #[test]
+    fn refuses_csv_without_header_or_user() {
+        assert!(from_csv("# nothing\n").is_err());
+
+        let error = from_csv("profile\nadmin\n").unwrap_err();
+        assert!(error.starts_with("row 1"), "{}", error);
+    }
+
+    #[test]
+    fn reads_yaml() {
+        let entries = read_roster(
+            "roster.yaml",
+            "users:\n  - user: alice\n    profile: admin\n    recipient: age1xyz\n",
+        )
+        .unwrap();
+
+        assert_eq!(entries[0].user, "alice");
+        assert_eq!(entries[0].recipient.as_deref(), Some("age1xyz"));
+    }
+
+    #[test]
+    fn refuses_users_sharing_a_file() {
+        for (name, content) in [
+            ("duplicate.csv", "user,profile\nalice,admin\nalice,admin\n"),
+            ("case.csv", "user,profile\nAlice,admin\nalice,admin\n"),
+            ("punctuation.csv", "user,profile\na.b,admin\na-b,admin\n"),
+            ("unnamed.csv", "user,profile\n...,admin\n"),
+        ] {
+            assert!(
+                matches!(
+                    read_roster(name, content),
+                    Err(CoralGateError::InvalidRoster { .. })
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_validities_a_certificate_can_not_have() {
+        for expire in ["forever", "100000d"] {
+            let content = format!("user,profile,expire\nalice,admin,{}\n", expire);
+
+            assert!(read_roster("expire.csv", &content).is_err(), "{}", expire);
+        }
+    }
+
+    #[test]
+    fn namespaced_profiles_need_user_bindings() {
+        let path =
+            std::env::temp_dir().join(format!("coralgate-{}-namespaced.csv", std::process::id()));
+        std::fs::write(&path, "user,profile,namespace\nbob,namespaced-edit,prod\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let error = read(path, BindingMode::Group).unwrap_err();
+        assert!(error.to_string().contains("bindingMode: user"), "{}", error);
+        assert!(read(path, BindingMode::User).is_ok());
+
+        std::fs::write(
+            path,
+            "user,profile,namespace,bindingMode\nbob,namespaced-edit,prod,user\n",
+        )
+        .unwrap();
+        assert!(read(path, BindingMode::Group).is_ok());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use crate::command::structure::ShiftUser;
+use crate::core::ical::CalendarEvent;
+use crate::core::profile::{self, ApplyOptions, SubjectBinding};
+use crate::core::{inventory, ledger, revocation};
+use crate::error::*;
+use crate::shared;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{ListParams, PostParams};
+use kube::{Api, Client};
+use serde::{Deserialize, Serialize};
+
+const SCHEDULE_KEY: &str = "schedule.json";
+
+/// One on-call shift, the user holds the profile from start to end
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Shift {
+    pub user: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// UID of the calendar event
+    pub uid: String,
+}
+
+impl Shift {
+    pub fn state(&self, now: DateTime<Utc>) -> &'static str {
+        if now < self.start {
+            "scheduled"
+        } else if now < self.end {
+            "active"
+        } else {
+            "ended"
+        }
+    }
+}
+
+/// Shifts imported for one profile, stored as a ConfigMap in the coralgate namespace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    /// Name of the ConfigMap, also the label value on the bindings
+    #[serde(skip)]
+    pub name: String,
+    pub profile: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// File the shifts came from
+    pub source: String,
+    pub imported_at: DateTime<Utc>,
+    pub shifts: Vec<Shift>,
+}
+
+/// One schedule per profile and namespace, importing again replaces it
+pub fn schedule_name(profile: &str, namespace: Option<&str>) -> String {
+    let name = match namespace {
+        Some(namespace) => format!("schedule-{}-{}", profile, namespace),
+        None => format!("schedule-{}", profile),
+    };
+
+    shared::sanitize_name(&name)
+}
+
+/// Turns expanded calendar events into shifts, returns the number of events
+/// skipped because they lack times or name no one
+pub fn shifts(events: &[CalendarEvent], user_from: ShiftUser) -> (Vec<Shift>, usize) {
+    let mut shifts = Vec::new();
+    let mut skipped = 0;
+
+    for event in events {
+        let user = match user_from {
+            ShiftUser::Summary => Some(event.summary.clone()).filter(|user| !user.is_empty()),
+            ShiftUser::Attendee => event.attendees.first().cloned(),
+        };
+
+        match (user, event.start, event.end) {
+            (Some(user), Some(start), Some(end)) if start < end => shifts.push(Shift {
+                user,
+                start,
+                end,
+                uid: event.uid.clone(),
+            }),
+            _ => skipped += 1,
+        }
+    }
+
+    shifts.sort_by_key(|shift| shift.start);
+    (shifts, skipped)
+}
+
+/// Creates or replaces the schedule
+pub async fn save(client: &Client, schedule: &Schedule) -> Result<()> {
+    ledger::ensure_namespace(client).await?;
+
+    let mut labels = shared::generate_profile_lables(&schedule.profile).unwrap_or_default();
+    labels.insert(shared::SCHEDULE_LABEL.into(), schedule.name.clone());
+
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+    let existing = api.get_opt(&schedule.name).await?;
+
+    let config_map = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(schedule.name.clone()),
+            namespace: Some(shared::NAMESPACE.into()),
+            labels: Some(labels),
+            resource_version: existing
+                .as_ref()
+                .and_then(|existing| existing.metadata.resource_version.clone()),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(
+            SCHEDULE_KEY.into(),
+            serde_json::to_string_pretty(schedule).unwrap_or_default(),
+        )])),
+        ..Default::default()
+    };
+
+    match existing {
+        Some(_) => {
+            api.replace(&schedule.name, &PostParams::default(), &config_map)
+                .await?
+        }
+        None => api.create(&PostParams::default(), &config_map).await?,
+    };
+
+    Ok(())
+}
+
+/// Every imported schedule
+pub async fn list(client: &Client) -> Result<Vec<Schedule>> {
+    let selector = format!(
+        "{},{}",
+        shared::label_selector(None),
+        shared::SCHEDULE_LABEL
+    );
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), shared::NAMESPACE);
+
+    let config_maps = match api.list(&ListParams::default().labels(&selector)).await {
+        Ok(config_maps) => config_maps,
+        Err(kube::Error::Api(status)) if status.is_not_found() => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut schedules = Vec::new();
+    for config_map in config_maps {
+        let Some(mut schedule) = config_map
+            .data
+            .as_ref()
+            .and_then(|data| data.get(SCHEDULE_KEY))
+            .and_then(|data| serde_json::from_str::<Schedule>(data).ok())
+        else {
+            continue;
+        };
+
+        schedule.name = config_map.metadata.name.unwrap_or_default();
+        schedules.push(schedule);
+    }
+
+    Ok(schedules)
+}
+
+/// What a reconcile changed
+#[derive(Debug, Default, Serialize)]
+pub struct Reconciled {
+    /// `binding: user until end` for everyone on shift
+    pub bound: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Binds everyone on shift now, until the end of the shift, and removes
+/// bindings of shifts that ended or are no longer in the schedule
+pub async fn reconcile(client: &Client, schedule: &Schedule) -> Result<Reconciled> {
+    let profile = profile::resolve(&schedule.profile, schedule.namespace.as_deref())?;
+    let revoked: Vec<String> = revocation::list(client)
+        .await?
+        .into_iter()
+        .map(|revocation| revocation.user)
+        .collect();
+
+    let now = Utc::now();
+    let mut on_shift: BTreeMap<&str, DateTime<Utc>> = BTreeMap::new();
+    for shift in &schedule.shifts {
+        if shift.state(now) != "active" || revoked.contains(&shift.user) {
+            continue;
+        }
+
+        let end = on_shift.entry(&shift.user).or_insert(shift.end);
+        *end = (*end).max(shift.end);
+    }
+
+    let mut reconciled = Reconciled::default();
+    let mut desired = BTreeSet::new();
+
+    for (user, end) in on_shift {
+        let mut subject = SubjectBinding::user(user);
+        subject.suffix = format!("{}-scheduled", subject.suffix);
+        subject
+            .labels
+            .insert(shared::SCHEDULE_LABEL.into(), schedule.name.clone());
+        subject
+            .annotations
+            .insert(shared::EXPIRES_ANNOTATION.into(), end.to_rfc3339());
+
+        for binding in profile.subject_bindings(&subject) {
+            binding.apply(client, &ApplyOptions::default()).await?;
+            reconciled.bound.push(format!(
+                "{}: {} until {}",
+                binding.id(),
+                user,
+                end.to_rfc3339()
+            ));
+            desired.insert(binding.id());
+        }
+    }
+
+    for binding in inventory::scheduled(client, &schedule.name).await? {
+        if desired.contains(&binding.to_string()) {
+            continue;
+        }
+
+        inventory::delete(client, &binding).await?;
+        reconciled.removed.push(binding.to_string());
+    }
+
+    Ok(reconciled)
+}
@@ -0,0 +1,195 @@
+use crate::core::access::{self, AccessCheck};
+use crate::core::profile::{self, Profile};
+use crate::error::*;
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Expectations for the profiles, read from a suite file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Suite {
+    pub profiles: Vec<ProfileSuite>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileSuite {
+    pub profile: String,
+    /// Group the profile binds, the requests are evaluated as a member of it.
+    /// Defaults to the group of the built-in profile with that name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub checks: Vec<AccessCheck>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub profile: String,
+    pub check: String,
+    pub expected: bool,
+    pub allowed: bool,
+    pub passed: bool,
+}
+
+impl Suite {
+    pub async fn read(path: &str) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path).await?;
+
+        serde_yaml::from_str(&content).map_err(|error| CoralGateError::InvalidSuite {
+            path: path.into(),
+            reason: error.to_string(),
+        })
+    }
+
+    /// The checks the built-in profiles ship with
+    pub fn from_profiles(profiles: &[Profile]) -> Self {
+        Self {
+            profiles: profiles
+                .iter()
+                .map(|profile| ProfileSuite {
+                    profile: profile.name().into(),
+                    group: Some(profile.group().into()),
+                    checks: profile.checks.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub async fn run(&self, client: &kube::Client) -> Result<Vec<CheckResult>> {
+        let mut results = Vec::new();
+
+        for suite in &self.profiles {
+            let group = match &suite.group {
+                Some(group) => group.clone(),
+                None => profile::builtin(&suite.profile)
+                    .map(|profile| profile.group().to_string())
+                    .ok_or_else(|| CoralGateError::UnknownProfile(suite.profile.clone()))?,
+            };
+
+            for check in &suite.checks {
+                let allowed = access::group_review(client, &group, check).await?;
+
+                results.push(CheckResult {
+                    profile: suite.profile.clone(),
+                    check: check.to_string(),
+                    expected: check.allowed,
+                    allowed,
+                    passed: allowed == check.allowed,
+                });
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+pub fn render_text(results: &[CheckResult]) -> String {
+    let mut report = String::new();
+
+    for result in results {
+        let status = if result.passed { "PASS" } else { "FAIL" };
+        report.push_str(&format!(
+            "{} {}: {}\n",
+            status, result.profile, result.check
+        ));
+    }
+
+    let failed = results.iter().filter(|result| !result.passed).count();
+    report.push_str(&format!(
+        "{} checks, {} passed, {} failed\n",
+        results.len(),
+        results.len() - failed,
+        failed
+    ));
+
+    report
+}
+
+pub fn render_json(results: &[CheckResult]) -> String {
+    serde_json::to_string_pretty(results).unwrap_or_default()
+}
+
+/// One testsuite per profile, one testcase per check
+pub fn render_junit(results: &[CheckResult]) -> String {
+    let profiles: BTreeSet<&str> = results
+        .iter()
+        .map(|result| result.profile.as_str())
+        .collect();
+
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+
+    for profile in profiles {
+        let cases: Vec<&CheckResult> = results
+            .iter()
+            .filter(|result| result.profile == profile)
+            .collect();
+        let failures = cases.iter().filter(|result| !result.passed).count();
+
+        report.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+            xml_escape(profile),
+            cases.len(),
+            failures
+        ));
+
+        for case in cases {
+            report.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\"",
+                xml_escape(profile),
+                xml_escape(&case.check)
+            ));
+
+            if case.passed {
+                report.push_str("/>\n");
+            } else {
+                report.push_str(&format!(
+                    ">\n      <failure message=\"expected allowed={}, got allowed={}\"/>\n    </testcase>\n",
+                    case.expected, case.allowed
+                ));
+            }
+        }
+
+        report.push_str("  </testsuite>\n");
+    }
+
+    report.push_str("</testsuites>\n");
+    report
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(profile: &str, check: &str, passed: bool) -> CheckResult {
+        CheckResult {
+            profile: profile.into(),
+            check: check.into(),
+            expected: true,
+            allowed: passed,
+            passed,
+        }
+    }
+
+    #[test]
+    fn groups_interleaved_profiles_into_one_testsuite() {
+        let report = render_junit(&[
+            result("admin", "list pods", true),
+            result("cluster-readonly", "list pods", true),
+            result("admin", "delete pods", false),
+        ]);
+
+        assert_eq!(report.matches("<testsuite ").count(), 2);
+        assert!(report.contains("<testsuite name=\"admin\" tests=\"2\" failures=\"1\">"));
+        assert!(
+            report.contains("<testsuite name=\"cluster-readonly\" tests=\"1\" failures=\"0\">")
+        );
+    }
+}
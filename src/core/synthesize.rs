@@ -0,0 +1,351 @@
+use crate::core::audit::AuditEvent;
+use crate::core::profile::ProfileDefinition;
+use crate::core::rbac::{self, EffectiveRule};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s_openapi::api::rbac::v1::PolicyRule;
+
+/// A request kind seen in the audit log
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Request {
+    /// Cluster wide when empty
+    pub namespace: Option<String>,
+    pub api_group: String,
+    /// `resource/subresource`
+    pub resource: String,
+    pub verb: String,
+}
+
+/// Everything an identity was allowed to do over the audit window
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub requests: BTreeSet<Request>,
+    /// `(path, verb)` of non resource requests
+    pub non_resource: BTreeSet<(String, String)>,
+}
+
+impl Usage {
+    /// Denied requests are left out, they are not something to grant
+    pub fn from_events(events: &[AuditEvent]) -> Self {
+        let mut usage = Self::default();
+
+        for event in events.iter().filter(|event| event.is_allowed()) {
+            match &event.object_ref {
+                Some(object) => {
+                    usage.requests.insert(Request {
+                        namespace: object.namespace.clone().filter(|ns| !ns.is_empty()),
+                        api_group: object.api_group.clone().unwrap_or_default(),
+                        resource: event.resource(),
+                        verb: event.verb.clone(),
+                    });
+                }
+                None => {
+                    usage
+                        .non_resource
+                        .insert((event.path().to_string(), event.verb.clone()));
+                }
+            }
+        }
+
+        usage
+    }
+}
+
+/// Merges requests into as few rules as possible: resources of one api group
+/// used with the same verbs share a rule
+fn collapse<'a>(requests: impl Iterator<Item = &'a Request>) -> Vec<PolicyRule> {
+    let mut verbs: BTreeMap<(&str, &str), BTreeSet<&str>> = BTreeMap::new();
+    for request in requests {
+        verbs
+            .entry((&request.api_group, &request.resource))
+            .or_default()
+            .insert(&request.verb);
+    }
+
+    let mut resources: BTreeMap<(&str, BTreeSet<&str>), Vec<String>> = BTreeMap::new();
+    for ((api_group, resource), verbs) in verbs {
+        resources
+            .entry((api_group, verbs))
+            .or_default()
+            .push(resource.to_string());
+    }
+
+    resources
+        .into_iter()
+        .map(|((api_group, verbs), resources)| PolicyRule {
+            api_groups: Some(vec![api_group.to_string()]),
+            resources: Some(resources),
+            verbs: verbs.into_iter().map(String::from).collect(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Least privilege profile covering exactly what was used, requests without a
+/// namespace and non resource requests need the ClusterRole
+pub fn synthesize(name: &str, group: Option<String>, usage: &Usage) -> ProfileDefinition {
+    let mut cluster_rules = collapse(
+        usage
+            .requests
+            .iter()
+            .filter(|request| request.namespace.is_none()),
+    );
+
+    let mut paths: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (path, verb) in &usage.non_resource {
+        paths.entry(verb).or_default().push(path.clone());
+    }
+    for (verb, paths) in paths {
+        cluster_rules.push(PolicyRule {
+            non_resource_urls: Some(paths),
+            verbs: vec![verb.to_string()],
+            ..Default::default()
+        });
+    }
+
+    let namespaces: BTreeSet<&String> = usage
+        .requests
+        .iter()
+        .filter_map(|request| request.namespace.as_ref())
+        .collect();
+
+    let namespaces = namespaces
+        .into_iter()
+        .map(|namespace| {
+            let rules = collapse(
+                usage
+                    .requests
+                    .iter()
+                    .filter(|request| request.namespace.as_ref() == Some(namespace)),
+            );
+
+            (namespace.clone(), rules)
+        })
+        .collect();
+
+    ProfileDefinition {
+        name: name.into(),
+        group,
+        cluster_rules,
+        namespaces,
+        ..Default::default()
+    }
+}
+
+fn wildcard_or(values: &[String], value: &str) -> bool {
+    values
+        .iter()
+        .any(|candidate| candidate == "*" || candidate == value)
+}
+
+fn is_used(
+    rule: &EffectiveRule,
+    usage: &Usage,
+    api_group: &str,
+    resource: &str,
+    verb: &str,
+) -> bool {
+    usage.requests.iter().any(|request| {
+        let namespace = rule.namespace.is_none() || rule.namespace == request.namespace;
+
+        namespace
+            && (api_group == "*" || request.api_group == api_group)
+            && (resource == "*" || request.resource == resource)
+            && (verb == "*" || request.verb == verb)
+    })
+}
+
+/// Granted permissions nothing in the audit window used, one line per rule
+pub fn unused(granted: &[EffectiveRule], usage: &Usage) -> Vec<String> {
+    let mut unused = Vec::new();
+
+    for effective in granted {
+        let rule = &effective.rule;
+        let scope = match &effective.namespace {
+            Some(namespace) => format!("namespace {}", namespace),
+            None => "cluster wide".into(),
+        };
+
+        if let Some(urls) = rule
+            .non_resource_urls
+            .as_ref()
+            .filter(|urls| !urls.is_empty())
+        {
+            let used = usage.non_resource.iter().any(|(path, verb)| {
+                wildcard_or(&rule.verbs, verb)
+                    && urls.iter().any(|url| match url.strip_suffix('*') {
+                        Some(prefix) => path.starts_with(prefix),
+                        None => url == path,
+                    })
+            });
+
+            if !used {
+                unused.push(format!(
+                    "{}: {}  via {}",
+                    scope,
+                    rbac::describe_rule(rule),
+                    effective.binding
+                ));
+            }
+            continue;
+        }
+
+        let mut unused_verbs = BTreeSet::new();
+        for verb in &rule.verbs {
+            let used = rule.api_groups.iter().flatten().any(|api_group| {
+                rule.resources
+                    .iter()
+                    .flatten()
+                    .any(|resource| is_used(effective, usage, api_group, resource, verb))
+            });
+
+            if !used {
+                unused_verbs.insert(verb.clone());
+            }
+        }
+
+        if !unused_verbs.is_empty() {
+            let unused_rule = PolicyRule {
+                verbs: unused_verbs.into_iter().collect(),
+                ..rule.clone()
+            };
+
+            unused.push(format!(
+                "{}: {}  via {}",
+                scope,
+                rbac::describe_rule(&unused_rule),
+                effective.binding
+            ));
+        }
+    }
+
+    unused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(namespace: Option<&str>, api_group: &str, resource: &str, verb: &str) -> Request {
+        Request {
+            namespace: namespace.map(String::from),
+            api_group: api_group.into(),
+            resource: resource.into(),
+            verb: verb.into(),
+        }
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn granted(namespace: Option<&str>, rule: PolicyRule) -> EffectiveRule {
+        EffectiveRule {
+            namespace: namespace.map(String::from),
+            rule,
+            binding: "ClusterRoleBinding/test".into(),
+            role: "ClusterRole/test".into(),
+        }
+    }
+
+    fn usage() -> Usage {
+        Usage {
+            requests: BTreeSet::from([
+                request(Some("apps"), "", "pods", "get"),
+                request(Some("apps"), "", "pods", "list"),
+                request(Some("apps"), "", "services", "list"),
+                request(Some("apps"), "", "services", "get"),
+                request(Some("apps"), "apps", "deployments", "patch"),
+                request(None, "", "nodes", "list"),
+            ]),
+            non_resource: BTreeSet::from([("/healthz".into(), "get".into())]),
+        }
+    }
+
+    #[test]
+    fn synthesizes_the_least_privilege() {
+        let definition = synthesize("observed", None, &usage());
+
+        assert_eq!(
+            definition.cluster_rules,
+            vec![
+                PolicyRule {
+                    api_groups: Some(strings(&[""])),
+                    resources: Some(strings(&["nodes"])),
+                    verbs: strings(&["list"]),
+                    ..Default::default()
+                },
+                PolicyRule {
+                    non_resource_urls: Some(strings(&["/healthz"])),
+                    verbs: strings(&["get"]),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(
+            definition.namespaces["apps"],
+            vec![
+                PolicyRule {
+                    api_groups: Some(strings(&[""])),
+                    resources: Some(strings(&["pods", "services"])),
+                    verbs: strings(&["get", "list"]),
+                    ..Default::default()
+                },
+                PolicyRule {
+                    api_groups: Some(strings(&["apps"])),
+                    resources: Some(strings(&["deployments"])),
+                    verbs: strings(&["patch"]),
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_unused_verbs_and_urls() {
+        let granted = vec![
+            granted(
+                Some("apps"),
+                PolicyRule {
+                    api_groups: Some(strings(&[""])),
+                    resources: Some(strings(&["pods"])),
+                    verbs: strings(&["get", "list", "delete"]),
+                    ..Default::default()
+                },
+            ),
+            granted(
+                None,
+                PolicyRule {
+                    api_groups: Some(strings(&["*"])),
+                    resources: Some(strings(&["*"])),
+                    verbs: strings(&["list"]),
+                    ..Default::default()
+                },
+            ),
+            granted(
+                None,
+                PolicyRule {
+                    non_resource_urls: Some(strings(&["/metrics", "/health*"])),
+                    verbs: strings(&["get"]),
+                    ..Default::default()
+                },
+            ),
+            granted(
+                None,
+                PolicyRule {
+                    non_resource_urls: Some(strings(&["/version"])),
+                    verbs: strings(&["get"]),
+                    ..Default::default()
+                },
+            ),
+        ];
+
+        let unused = unused(&granted, &usage());
+
+        assert_eq!(unused.len(), 2, "{:?}", unused);
+        assert!(unused[0].starts_with("namespace apps: "), "{}", unused[0]);
+        assert!(unused[0].contains("delete") && !unused[0].contains("list"));
+        assert!(unused[1].contains("/version"), "{}", unused[1]);
+    }
+}
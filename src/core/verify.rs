@@ -0,0 +1,99 @@
+use crate::core::{access, certificate, profile::Profile};
+use crate::error::*;
+use crate::output::say;
+
+use k8s_openapi::api::authentication::v1::SelfSubjectReview;
+use kube::api::PostParams;
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::{Api, Client};
+
+/// Proves a freshly issued kubeconfig works: the certificate chains to the
+/// cluster CA, the api server sees the expected identity and the profile
+/// checks hold. `group` is the group the certificate must carry, if any
+#[tracing::instrument(name = "verify", skip_all)]
+pub async fn verify_kubeconfig(
+    kubeconfig_yaml: &str,
+    user: &str,
+    group: Option<&str>,
+    profile: &Profile,
+    signed_cert_pem: &[u8],
+    roots: &[Vec<u8>],
+) -> Result<()> {
+    let mut failures = Vec::new();
+
+    let chain = certificate::pem_to_der(signed_cert_pem)?;
+    if let Some((leaf, intermediates)) = chain.split_first()
+        && let Err(error) = certificate::verify_chain(leaf, intermediates, roots)
+    {
+        failures.push(error.to_string());
+    }
+
+    // A certificate that does not chain is rejected by the api server too,
+    // its error must not hide the reason
+    if let Err(error) = review(kubeconfig_yaml, user, group, profile, &mut failures).await {
+        if failures.is_empty() {
+            return Err(error);
+        }
+        failures.push(error.to_string());
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(CoralGateError::VerificationFailed(failures.join("; ")))
+    }
+}
+
+/// Asks the api server who the kubeconfig is and what it may do, mismatches
+/// go to `failures`
+async fn review(
+    kubeconfig_yaml: &str,
+    user: &str,
+    group: Option<&str>,
+    profile: &Profile,
+    failures: &mut Vec<String>,
+) -> Result<()> {
+    let kubeconfig = Kubeconfig::from_yaml(kubeconfig_yaml)?;
+    let config =
+        kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default()).await?;
+    let client = Client::try_from(config)?;
+
+    let api: Api<SelfSubjectReview> = Api::all(client.clone());
+    let review = api
+        .create(&PostParams::default(), &SelfSubjectReview::default())
+        .await?;
+    let user_info = review
+        .status
+        .and_then(|status| status.user_info)
+        .unwrap_or_default();
+
+    let username = user_info.username.unwrap_or_default();
+    if username != user {
+        failures.push(format!(
+            "api server sees user {:?}, expected {:?}",
+            username, user
+        ));
+    }
+
+    let groups = user_info.groups.unwrap_or_default();
+    if let Some(group) = group
+        && !groups.iter().any(|candidate| candidate == group)
+    {
+        failures.push(format!(
+            "api server sees groups {:?}, expected {:?} among them",
+            groups, group
+        ));
+    }
+
+    for check in &profile.checks {
+        let allowed = access::self_review(&client, check).await?;
+
+        if allowed == check.allowed {
+            say!("verified: {} {}", user, check);
+        } else {
+            failures.push(format!("{} {}, but the api server disagrees", user, check));
+        }
+    }
+
+    Ok(())
+}
@@ -11,8 +11,8 @@ pub enum CoralGateError {
     #[error("Timeout Error: {0}")]
     TimeoutError(String),
 
-    #[error("Unknown Temp Error")]
-    UnknownTempError,
+    #[error("Cancelled")]
+    Cancelled,
 
     #[error(transparent)]
     IOError(#[from] Box<Error>),
@@ -26,9 +26,113 @@ pub enum CoralGateError {
     #[error("Kube Api Error {0}")]
     KubeApiError(#[from] kube::Error),
 
-    #[error("Kubeconfig Error")]
+    #[error("Kubeconfig Error: {0}")]
     KubeConfigError(#[from] kube::config::KubeconfigError),
 
+    #[error("{0} exists but was not created by coralgate, use --adopt to take it over")]
+    NotOwned(String),
+
+    #[error(
+        "{resource} has fields managed by someone else: {conflicts}. Use --adopt to overwrite them"
+    )]
+    FieldManagerConflict { resource: String, conflicts: String },
+
+    #[error("Can not parse certificate: {0}")]
+    CertificateParseError(String),
+
+    #[error("{0} needs the cluster and can not be used when signing with a local CA")]
+    OfflineUnsupported(String),
+
+    #[error("Invalid CA {path}: {reason}")]
+    InvalidCa { path: String, reason: String },
+
+    #[error("Can not generate the key or CSR: {0}")]
+    KeyGenerationFailed(String),
+
+    #[error("Can not sign the certificate: {0}")]
+    SigningFailed(String),
+
+    #[error("Invalid signer {signer}: {reason}")]
+    InvalidSigner { signer: String, reason: String },
+
+    #[error("CSR {name} was rejected, {reason}")]
+    CsrRejected { name: String, reason: String },
+
+    #[error("The issued certificate lacks the requested usages: {0}")]
+    MissingUsages(String),
+
+    #[error("Certificate chain error: {0}")]
+    CertificateChainError(String),
+
+    #[error("The issued kubeconfig does not work as intended: {0}")]
+    VerificationFailed(String),
+
+    #[error("Invalid suite file {path}: {reason}")]
+    InvalidSuite { path: String, reason: String },
+
+    #[error("Profile {profile} has unacknowledged high risk findings: {findings}")]
+    HighRiskProfile { profile: String, findings: String },
+
+    #[error("Invalid profile file {path}: {reason}")]
+    InvalidProfile { path: String, reason: String },
+
+    #[error("Notification hook {command:?} failed with {status}")]
+    HookFailed { command: String, status: String },
+
+    #[error("Invalid roster {path}: {reason}")]
+    InvalidRoster { path: String, reason: String },
+
+    #[error("Invalid calendar {path}: {reason}")]
+    InvalidCalendar { path: String, reason: String },
+
+    #[error("Can not encrypt for {recipient}: {reason}")]
+    EncryptionFailed { recipient: String, reason: String },
+
+    #[error("{0} roster users failed")]
+    BatchFailed(usize),
+
+    #[error("A reason is required")]
+    MissingReason,
+
+    #[error("Invalid duration: {0}")]
+    InvalidDuration(String),
+
+    #[error("{0}")]
+    InvalidArguments(String),
+
+    #[error("Invalid user {0:?}, it has no letters or digits to name objects after")]
+    InvalidUser(String),
+
+    #[error("User {0} is revoked, run unrevoke first")]
+    Revoked(String),
+
+    #[error("No grant with certificate serial {0} in the ledger")]
+    UnknownSerial(String),
+
+    #[error("Unknown profile {0}")]
+    UnknownProfile(String),
+
+    #[error("{0} profile checks failed")]
+    ProfileTestFailed(usize),
+
+    #[error("{0} doctor checks failed")]
+    DoctorFailed(usize),
+
+    #[error("{0} kubeconfigs could not be refreshed")]
+    RefreshFailed(usize),
+
+    #[error("{0} kubeconfigs hold client certificates the CA bundle does not trust")]
+    UntrustedCertificates(usize),
+
+    #[error("The kubeconfig's current user has no client certificate")]
+    KubeconfigWithoutCertificate,
+
+    #[error("Invalid RBAC snapshot {path}: {reason}")]
+    InvalidSnapshot { path: String, reason: String },
+
+    #[error("Can not write {path}: {reason}")]
+    WriteFailed { path: String, reason: String },
+
     #[error("Can not get root CA from config")]
     ClientManagerRootCaMissing,
 
@@ -44,3 +148,216 @@ pub enum CoralGateError {
     #[error("Error output to utf8 {0}")]
     CommandOutputUtf8Error(#[from] FromUtf8Error),
 }
+
+/// Exit codes, kept stable for scripts. 2 is also what clap uses for bad arguments
+pub mod exit_code {
+    pub const FAILURE: u8 = 1;
+    pub const INVALID_INPUT: u8 = 2;
+    pub const KUBECONFIG: u8 = 3;
+    pub const API: u8 = 4;
+    pub const PERMISSION_DENIED: u8 = 5;
+    pub const ISSUANCE: u8 = 6;
+    pub const REFUSED: u8 = 7;
+    pub const PARTIAL_FAILURE: u8 = 8;
+    pub const EXTERNAL_COMMAND: u8 = 9;
+    pub const CANCELLED: u8 = 130;
+}
+
+impl CoralGateError {
+    /// Stable identifier of the failure for `--output json`
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoralGateError::MissingEnvironment(_) => "missing-environment",
+            CoralGateError::TimeoutError(_) => "timeout",
+            CoralGateError::Cancelled => "cancelled",
+            CoralGateError::IOError(_) => "io",
+            CoralGateError::MissingName(_) => "missing-name",
+            CoralGateError::MissingNamespace(_) => "missing-namespace",
+            CoralGateError::KubeApiError(kube::Error::Api(status)) if status.code == 401 => {
+                "unauthorized"
+            }
+            CoralGateError::KubeApiError(kube::Error::Api(status)) if status.is_forbidden() => {
+                "forbidden"
+            }
+            CoralGateError::KubeApiError(kube::Error::Api(_)) => "kube-api",
+            CoralGateError::KubeApiError(_) => "kube-connection",
+            CoralGateError::KubeConfigError(_) => "kubeconfig",
+            CoralGateError::NotOwned(_) => "not-owned",
+            CoralGateError::FieldManagerConflict { .. } => "field-manager-conflict",
+            CoralGateError::CertificateParseError(_) => "certificate-parse",
+            CoralGateError::OfflineUnsupported(_) => "offline-unsupported",
+            CoralGateError::InvalidCa { .. } => "invalid-ca",
+            CoralGateError::KeyGenerationFailed(_) => "key-generation-failed",
+            CoralGateError::SigningFailed(_) => "signing-failed",
+            CoralGateError::InvalidSigner { .. } => "invalid-signer",
+            CoralGateError::CsrRejected { .. } => "csr-rejected",
+            CoralGateError::MissingUsages(_) => "missing-usages",
+            CoralGateError::CertificateChainError(_) => "certificate-chain",
+            CoralGateError::VerificationFailed(_) => "verification-failed",
+            CoralGateError::InvalidSuite { .. } => "invalid-suite",
+            CoralGateError::HighRiskProfile { .. } => "high-risk-profile",
+            CoralGateError::InvalidProfile { .. } => "invalid-profile",
+            CoralGateError::HookFailed { .. } => "hook-failed",
+            CoralGateError::InvalidRoster { .. } => "invalid-roster",
+            CoralGateError::InvalidCalendar { .. } => "invalid-calendar",
+            CoralGateError::EncryptionFailed { .. } => "encryption-failed",
+            CoralGateError::BatchFailed(_) => "batch-failed",
+            CoralGateError::MissingReason => "missing-reason",
+            CoralGateError::InvalidDuration(_) => "invalid-duration",
+            CoralGateError::InvalidArguments(_) => "invalid-arguments",
+            CoralGateError::InvalidUser(_) => "invalid-user",
+            CoralGateError::Revoked(_) => "revoked",
+            CoralGateError::UnknownSerial(_) => "unknown-serial",
+            CoralGateError::UnknownProfile(_) => "unknown-profile",
+            CoralGateError::ProfileTestFailed(_) => "profile-test-failed",
+            CoralGateError::DoctorFailed(_) => "doctor-failed",
+            CoralGateError::RefreshFailed(_) => "refresh-failed",
+            CoralGateError::UntrustedCertificates(_) => "untrusted-certificates",
+            CoralGateError::KubeconfigWithoutCertificate => "kubeconfig-without-certificate",
+            CoralGateError::InvalidSnapshot { .. } => "invalid-snapshot",
+            CoralGateError::WriteFailed { .. } => "write-failed",
+            CoralGateError::ClientManagerRootCaMissing => "root-ca-missing",
+            CoralGateError::ClientManagerConfigNotInitialized => "config-not-initialized",
+            CoralGateError::BinaryNotFound { .. } => "binary-not-found",
+            CoralGateError::CommandOutputError(_) => "io",
+            CoralGateError::CommandOutputUtf8Error(_) => "invalid-utf8",
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CoralGateError::Cancelled => exit_code::CANCELLED,
+
+            CoralGateError::MissingEnvironment(_)
+            | CoralGateError::MissingNamespace(_)
+            | CoralGateError::OfflineUnsupported(_)
+            | CoralGateError::InvalidCa { .. }
+            | CoralGateError::InvalidSigner { .. }
+            | CoralGateError::InvalidSuite { .. }
+            | CoralGateError::InvalidProfile { .. }
+            | CoralGateError::InvalidRoster { .. }
+            | CoralGateError::InvalidCalendar { .. }
+            | CoralGateError::MissingReason
+            | CoralGateError::InvalidDuration(_)
+            | CoralGateError::InvalidArguments(_)
+            | CoralGateError::InvalidUser(_)
+            | CoralGateError::UnknownSerial(_)
+            | CoralGateError::UnknownProfile(_)
+            | CoralGateError::InvalidSnapshot { .. } => exit_code::INVALID_INPUT,
+
+            CoralGateError::KubeConfigError(_)
+            | CoralGateError::KubeconfigWithoutCertificate
+            | CoralGateError::ClientManagerRootCaMissing
+            | CoralGateError::ClientManagerConfigNotInitialized => exit_code::KUBECONFIG,
+
+            CoralGateError::KubeApiError(kube::Error::Api(status))
+                if status.code == 401 || status.is_forbidden() =>
+            {
+                exit_code::PERMISSION_DENIED
+            }
+            CoralGateError::NotOwned(_) | CoralGateError::FieldManagerConflict { .. } => {
+                exit_code::PERMISSION_DENIED
+            }
+            CoralGateError::KubeApiError(_) => exit_code::API,
+
+            CoralGateError::TimeoutError(_)
+            | CoralGateError::KeyGenerationFailed(_)
+            | CoralGateError::SigningFailed(_)
+            | CoralGateError::CsrRejected { .. }
+            | CoralGateError::MissingUsages(_)
+            | CoralGateError::CertificateChainError(_)
+            | CoralGateError::VerificationFailed(_) => exit_code::ISSUANCE,
+
+            CoralGateError::Revoked(_) | CoralGateError::HighRiskProfile { .. } => {
+                exit_code::REFUSED
+            }
+
+            CoralGateError::BatchFailed(_)
+            | CoralGateError::ProfileTestFailed(_)
+            | CoralGateError::DoctorFailed(_)
+            | CoralGateError::RefreshFailed(_)
+            | CoralGateError::UntrustedCertificates(_) => exit_code::PARTIAL_FAILURE,
+
+            CoralGateError::HookFailed { .. }
+            | CoralGateError::EncryptionFailed { .. }
+            | CoralGateError::BinaryNotFound { .. } => exit_code::EXTERNAL_COMMAND,
+
+            CoralGateError::IOError(_)
+            | CoralGateError::WriteFailed { .. }
+            | CoralGateError::MissingName(_)
+            | CoralGateError::CertificateParseError(_)
+            | CoralGateError::CommandOutputError(_)
+            | CoralGateError::CommandOutputUtf8Error(_) => exit_code::FAILURE,
+        }
+    }
+
+    /// What to do about the failure, when there is something to say
+    pub fn hint(&self) -> Option<String> {
+        let hint = match self {
+            CoralGateError::KubeApiError(kube::Error::Api(status))
+                if status.is_forbidden() && status.message.contains("approve") =>
+            {
+                format!(
+                    "your kubeconfig lacks permission to approve CSRs for signer {}, it needs the approve verb on signers.certificates.k8s.io, or pass --no-approve and let the signer's approver do it",
+                    signer_from(&status.message).unwrap_or("in use")
+                )
+            }
+            CoralGateError::KubeApiError(kube::Error::Api(status)) if status.is_forbidden() => {
+                "your kubeconfig lacks permission for this request, coralgate needs a kubeconfig that can manage RBAC and CSRs".into()
+            }
+            CoralGateError::KubeApiError(kube::Error::Api(status)) if status.code == 401 => {
+                "the API server rejected the kubeconfig's credentials, they may have expired".into()
+            }
+            CoralGateError::KubeApiError(kube::Error::Api(_)) => return None,
+            CoralGateError::KubeApiError(_) => {
+                "the API server could not be reached, check the server URL in the kubeconfig".into()
+            }
+            CoralGateError::KubeConfigError(_)
+            | CoralGateError::ClientManagerConfigNotInitialized => {
+                "pass --kubeconfig or set KUBECONFIG to a readable kubeconfig".into()
+            }
+            CoralGateError::ClientManagerRootCaMissing => {
+                "no cluster CA was found in the kubeconfig, kube-root-ca.crt or cluster-info, pass --ca-file".into()
+            }
+            CoralGateError::TimeoutError(_) => {
+                "the CSR was not signed in time, with --no-approve or a custom signer check that its approver and signer are running, or wait longer with --wait".into()
+            }
+            CoralGateError::CsrRejected { .. } => {
+                "see the CSR's conditions with kubectl describe csr, the signer or an approval policy refused it".into()
+            }
+            CoralGateError::MissingUsages(_) => {
+                "the signer does not honour these usages, request fewer with --usages or use another --signer-name".into()
+            }
+            CoralGateError::NotOwned(_) | CoralGateError::FieldManagerConflict { .. } => {
+                "pass --adopt to let coralgate manage the object".into()
+            }
+            CoralGateError::MissingNamespace(_) => "pass -n/--namespace".into(),
+            CoralGateError::InvalidArguments(_) => "run the command with --help to see its usage".into(),
+            CoralGateError::Revoked(user) => format!("run coralgate unrevoke --user {}", user),
+            CoralGateError::EncryptionFailed { .. } => {
+                "install age (https://age-encryption.org) and check the recipient".into()
+            }
+            CoralGateError::MissingEnvironment(_) => {
+                "set the variable or pass the path without ~".into()
+            }
+            CoralGateError::UntrustedCertificates(_) => {
+                "issue them again, the check uses the bundle the API server serves with and is not reliable on clusters with a separate client CA".into()
+            }
+            CoralGateError::WriteFailed { .. } => {
+                "check that the directory exists and is writable".into()
+            }
+            CoralGateError::BatchFailed(_) => {
+                "see the summary above, issued users are kept and failed ones rolled back".into()
+            }
+            _ => return None,
+        };
+
+        Some(hint)
+    }
+}
+
+/// Signer named in a forbidden approval, `... with signerName "x"`
+fn signer_from(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("signerName")?;
+    rest.split('"').nth(1)
+}
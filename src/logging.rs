@@ -0,0 +1,109 @@
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Flushes exported spans when dropped, keep it until the command is done
+pub struct Guard {
+    #[cfg(feature = "otlp")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.provider.take()
+            && let Err(error) = provider.shutdown()
+        {
+            eprintln!("could not flush traces: {}", error);
+        }
+    }
+}
+
+/// Only coralgate's warnings by default, failures are reported as errors
+/// already. `-v` adds coralgate's steps, `-vv` their details and the kube
+/// client, `-vvv` everything. `RUST_LOG` overrides it
+fn directives(verbosity: u8) -> &'static str {
+    match verbosity {
+        0 => "coralgate=warn",
+        1 => "coralgate=info",
+        2 => "info,coralgate=debug",
+        _ => "trace",
+    }
+}
+
+/// Logs go to stderr, stdout is left to command output
+pub fn init(verbosity: u8, format: LogFormat, otlp_endpoint: Option<&str>) -> Guard {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(directives(verbosity)));
+
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(verbosity > 1);
+    let layer = match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    };
+
+    let registry = tracing_subscriber::registry().with(layer.with_filter(filter));
+
+    #[cfg(feature = "otlp")]
+    if let Some(endpoint) = otlp_endpoint {
+        match otlp::provider(endpoint) {
+            Ok(provider) => {
+                registry.with(otlp::layer(&provider)).init();
+                return Guard {
+                    provider: Some(provider),
+                };
+            }
+            Err(error) => {
+                registry.init();
+                tracing::warn!(endpoint, %error, "could not set up trace export");
+                return Guard { provider: None };
+            }
+        }
+    }
+
+    registry.init();
+
+    Guard {
+        #[cfg(feature = "otlp")]
+        provider: None,
+    }
+}
+
+#[cfg(feature = "otlp")]
+mod otlp {
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+    use tracing_subscriber::{EnvFilter, Layer, registry::LookupSpan};
+
+    pub fn provider(
+        endpoint: &str,
+    ) -> Result<SdkTracerProvider, opentelemetry_otlp::ExporterBuildError> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?;
+
+        Ok(SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name("coralgate").build())
+            .build())
+    }
+
+    /// Exports coralgate's spans whatever the verbosity
+    pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("coralgate"))
+            .with_filter(EnvFilter::new("coralgate=info"))
+    }
+}
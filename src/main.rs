@@ -1,24 +1,151 @@
 mod command;
 mod core;
 mod error;
+mod logging;
+mod output;
 mod shared;
 
 use clap::Parser;
 use error::*;
-
-/// TODO: add logging
+use std::process::ExitCode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli_arguments = command::structure::Cli::parse();
-    // TODO: add tokio select for gracefull shutdown
-    match cli_arguments.command {
+async fn main() -> ExitCode {
+    let cli_arguments = match command::structure::Cli::try_parse() {
+        Ok(cli_arguments) => cli_arguments,
+        Err(error) => return usage_error(error),
+    };
+    output::set(cli_arguments.output);
+
+    #[cfg(feature = "otlp")]
+    let otlp_endpoint = cli_arguments.otlp_endpoint.as_deref();
+    #[cfg(not(feature = "otlp"))]
+    let otlp_endpoint = None;
+    let _guard = logging::init(
+        cli_arguments.verbose,
+        cli_arguments.log_format,
+        otlp_endpoint,
+    );
+
+    let result = tokio::select! {
+        result = run(cli_arguments.command) => result,
+        _ = tokio::signal::ctrl_c() => Err(CoralGateError::Cancelled),
+    };
+
+    // Whatever the failed or cancelled command created and did not commit
+    if result.is_err()
+        && let Some(client) = core::client::ClientManager::client()
+    {
+        let (removed, failed) = core::rollback::unwind(client).await;
+        for object in removed {
+            eprintln!("rolled back {}", object);
+        }
+        for (object, error) in failed {
+            tracing::warn!(%error, "could not roll back {}", object);
+        }
+    }
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            output::error(&error);
+            // The runtime would wait for a prompt still reading stdin
+            if matches!(error, CoralGateError::Cancelled) {
+                drop(_guard);
+                std::process::exit(error.exit_code().into());
+            }
+            ExitCode::from(error.exit_code())
+        }
+    }
+}
+
+/// Usage errors follow `--output json` like any other failure, help and
+/// version are printed by clap
+fn usage_error(error: clap::Error) -> ExitCode {
+    use clap::error::ErrorKind;
+
+    let arguments: Vec<String> = std::env::args().collect();
+    let json = arguments
+        .windows(2)
+        .any(|pair| pair[0] == "--output" && pair[1] == "json")
+        || arguments.iter().any(|argument| argument == "--output=json");
+
+    if !json
+        || matches!(
+            error.kind(),
+            ErrorKind::DisplayHelp
+                | ErrorKind::DisplayVersion
+                | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        )
+    {
+        error.exit();
+    }
+
+    output::set(output::OutputFormat::Json);
+    // Only the error itself, without clap's usage and tips
+    let rendered = error.render().to_string();
+    let message = rendered
+        .lines()
+        .take_while(|line| !line.starts_with("Usage:"))
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("tip:"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let message = message.trim_start_matches("error: ");
+
+    let error = CoralGateError::InvalidArguments(message.into());
+    output::error(&error);
+    ExitCode::from(error.exit_code())
+}
+
+async fn run(command: command::structure::Commands) -> Result<()> {
+    match command {
         command::structure::Commands::Generate(gen_arguments) => {
             command::generate::handle(gen_arguments).await?
         }
         command::structure::Commands::Setup(setup_arguments) => {
             command::setup::handle(setup_arguments).await?
         }
+        command::structure::Commands::Teardown(teardown_arguments) => {
+            command::teardown::handle(teardown_arguments).await?
+        }
+        command::structure::Commands::Profile(profile_arguments) => {
+            command::profile::handle(profile_arguments).await?
+        }
+        command::structure::Commands::Explain(explain_arguments) => {
+            command::explain::handle(explain_arguments).await?
+        }
+        command::structure::Commands::Report(report_arguments) => {
+            command::report::handle(report_arguments).await?
+        }
+        command::structure::Commands::Activity(activity_arguments) => {
+            command::activity::handle(activity_arguments).await?
+        }
+        command::structure::Commands::Revoke(revoke_arguments) => {
+            command::revoke::handle(revoke_arguments).await?
+        }
+        command::structure::Commands::Unrevoke(unrevoke_arguments) => {
+            command::unrevoke::handle(unrevoke_arguments).await?
+        }
+        command::structure::Commands::List(list_arguments) => {
+            command::list::handle(list_arguments).await?
+        }
+        command::structure::Commands::BreakGlass(break_glass_arguments) => {
+            command::break_glass::handle(break_glass_arguments).await?
+        }
+        command::structure::Commands::Elevate(elevate_arguments) => {
+            command::elevate::handle(elevate_arguments).await?
+        }
+        command::structure::Commands::Gc(gc_arguments) => command::gc::handle(gc_arguments).await?,
+        command::structure::Commands::Schedule(schedule_arguments) => {
+            command::schedule::handle(schedule_arguments).await?
+        }
+        command::structure::Commands::Doctor(doctor_arguments) => {
+            command::doctor::handle(doctor_arguments).await?
+        }
+        command::structure::Commands::RefreshCa(refresh_ca_arguments) => {
+            command::refresh_ca::handle(refresh_ca_arguments).await?
+        }
     }
 
     Ok(())
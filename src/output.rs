@@ -0,0 +1,98 @@
+use crate::error::*;
+
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+static STDOUT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+pub fn set(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// Marks stdout as carrying a result in text mode too, like YAML meant to be piped
+pub fn take_stdout() {
+    STDOUT_TAKEN.store(true, Ordering::Relaxed);
+}
+
+/// Whether human output has to stay off stdout
+pub fn stdout_taken() -> bool {
+    json() || STDOUT_TAKEN.load(Ordering::Relaxed)
+}
+
+/// Prints human output, on stderr when stdout carries json or another result
+macro_rules! say {
+    ($($argument:tt)*) => {
+        if $crate::output::stdout_taken() {
+            eprintln!($($argument)*)
+        } else {
+            println!($($argument)*)
+        }
+    };
+}
+pub(crate) use say;
+
+/// Prints the result of a command with `--output json`, text output is
+/// printed by the command as it goes
+pub fn result<T: Serialize>(value: &T) {
+    if json() {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorOutput<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorDetail<'a> {
+    code: &'a str,
+    exit_code: u8,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+/// Reports the error that ended the command on stderr, so stdout only ever
+/// carries results
+pub fn error(error: &CoralGateError) {
+    let hint = error.hint();
+
+    if json() {
+        let output = ErrorOutput {
+            error: ErrorDetail {
+                code: error.code(),
+                exit_code: error.exit_code(),
+                message: error.to_string(),
+                hint,
+            },
+        };
+        eprintln!(
+            "{}",
+            serde_json::to_string_pretty(&output).unwrap_or_default()
+        );
+        return;
+    }
+
+    eprintln!("error: {}", error);
+    if let Some(hint) = hint {
+        eprintln!("hint: {}", hint);
+    }
+}
@@ -1,10 +1,281 @@
-use std::collections::BTreeMap;
+use crate::error::{self, CoralGateError};
+
+use std::{collections::BTreeMap, env, path::PathBuf};
+
+use sha2::{Digest, Sha256};
+
+pub const CREATED_BY_LABEL: &str = "created-by";
+pub const CREATED_BY_VALUE: &str = "coralgate";
+pub const PROFILE_LABEL: &str = "coralgate/profile";
+/// Sanitized name of the user an object was created for
+pub const USER_LABEL: &str = "coralgate/user";
+/// Set on everything issued through break-glass
+pub const BREAK_GLASS_LABEL: &str = "coralgate/break-glass";
+/// Justification given when issuing a credential
+pub const REASON_ANNOTATION: &str = "coralgate/reason";
+/// Set on temporary bindings made by `elevate`
+pub const ELEVATION_LABEL: &str = "coralgate/elevation";
+/// Schedule a ConfigMap holds or a binding was made for
+pub const SCHEDULE_LABEL: &str = "coralgate/schedule";
+/// RFC 3339 time after which `gc` removes a temporary binding
+pub const EXPIRES_ANNOTATION: &str = "coralgate/expires-at";
+/// RFC 3339 time after which `gc` removes a CSR kept for retention
+pub const DELETE_AFTER_ANNOTATION: &str = "coralgate/delete-after";
+/// RFC 3339 time until which the issuing command waits for the signer, an
+/// unsigned CSR is only stale after it
+pub const SIGN_BY_ANNOTATION: &str = "coralgate/sign-by";
+
+/// Namespace holding coralgate's own bookkeeping
+pub const NAMESPACE: &str = "coralgate";
+
+/// Field manager used for every server side apply
+pub const FIELD_MANAGER: &str = "coralgate";
 
 /// TODO: Move to shared or utils
 pub fn generate_lables() -> Option<BTreeMap<std::string::String, std::string::String>> {
     let mut labels: BTreeMap<String, String> = BTreeMap::new();
 
-    labels.insert("created-by".into(), "coralgate".into());
+    labels.insert(CREATED_BY_LABEL.into(), CREATED_BY_VALUE.into());
+
+    Some(labels)
+}
+
+/// Labels for objects that belong to a single profile
+pub fn generate_profile_lables(profile: &str) -> Option<BTreeMap<String, String>> {
+    let mut labels = generate_lables().unwrap_or_default();
+
+    labels.insert(PROFILE_LABEL.into(), profile.into());
 
     Some(labels)
 }
+
+/// Label selector matching everything coralgate created, optionally for one profile
+pub fn label_selector(profile: Option<&str>) -> String {
+    match profile {
+        Some(profile) => format!(
+            "{}={},{}={}",
+            CREATED_BY_LABEL, CREATED_BY_VALUE, PROFILE_LABEL, profile
+        ),
+        None => format!("{}={}", CREATED_BY_LABEL, CREATED_BY_VALUE),
+    }
+}
+
+/// Asks a yes/no question on the terminal, anything but yes is a no
+pub async fn confirm(question: &str) -> std::io::Result<bool> {
+    use std::io::Write;
+
+    // stdout carries the result with --output json
+    if crate::output::json() {
+        eprint!("{} [y/N] ", question);
+        std::io::stderr().flush()?;
+    } else {
+        print!("{} [y/N] ", question);
+        std::io::stdout().flush()?;
+    }
+
+    // Off the runtime threads, so Ctrl-C is still noticed while waiting
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).map(|_| answer)
+    })
+    .await
+    .map_err(std::io::Error::other)??;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Makes a value usable in object names and label values, lowercase
+/// alphanumerics and dashes only
+pub fn sanitize_name(value: &str) -> String {
+    let sanitized: String = value
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+
+    let sanitized: String = sanitized.chars().take(63).collect();
+
+    sanitized.trim_matches('-').to_string()
+}
+
+/// Hex SHA-256 of the value, for keys that must tell apart names
+/// `sanitize_name` maps to the same string
+pub fn digest(value: &str) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Label value or name suffix for a user or group: the sanitized name, kept
+/// readable, and a short hash of the exact name so `Alice` and `alice` differ
+pub fn unique_label(value: &str) -> String {
+    let hash = &digest(value)[..8];
+    let name: String = sanitize_name(value).chars().take(54).collect();
+
+    match name.trim_end_matches('-') {
+        "" => hash.to_string(),
+        name => format!("{}-{}", name, hash),
+    }
+}
+
+/// Selects objects of a user, labelled by [`unique_label`] or, before it
+/// existed, by the sanitized name alone. Several users may match the latter
+pub fn user_selector(user: &str) -> String {
+    match sanitize_name(user).as_str() {
+        "" => format!("{}={}", USER_LABEL, unique_label(user)),
+        sanitized => format!("{} in ({},{})", USER_LABEL, sanitized, unique_label(user)),
+    }
+}
+
+/// Parses durations like `30m`, `1h`, `1h30m` or `2w`, for command line arguments
+pub fn parse_duration(value: &str) -> Result<chrono::Duration, String> {
+    let mut total = chrono::Duration::zero();
+    let mut number = String::new();
+
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let amount: i64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration {:?}, expected e.g. 30m or 1h", value))?;
+        number.clear();
+
+        let part = match c {
+            's' => chrono::Duration::try_seconds(amount),
+            'm' => chrono::Duration::try_minutes(amount),
+            'h' => chrono::Duration::try_hours(amount),
+            'd' => chrono::Duration::try_days(amount),
+            'w' => chrono::Duration::try_weeks(amount),
+            _ => return Err(format!("invalid duration unit {:?} in {:?}", c, value)),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(|| format!("duration {:?} is too long", value))?;
+    }
+
+    if !number.is_empty() || total.is_zero() {
+        return Err(format!(
+            "invalid duration {:?}, expected e.g. 30m or 1h",
+            value
+        ));
+    }
+
+    Ok(total)
+}
+
+/// The time `duration` from now, durations reaching past what a date can
+/// hold are refused
+pub fn from_now(duration: chrono::Duration) -> error::Result<chrono::DateTime<chrono::Utc>> {
+    chrono::Utc::now()
+        .checked_add_signed(duration)
+        .ok_or_else(|| {
+            CoralGateError::InvalidDuration(format!("{}s is too long", duration.num_seconds()))
+        })
+}
+
+/// `expirationSeconds` of a CSR, which only holds an i32
+pub fn expiration_seconds(duration: chrono::Duration) -> error::Result<i32> {
+    i32::try_from(duration.num_seconds()).map_err(|_| {
+        CoralGateError::InvalidDuration(format!(
+            "{}s is longer than a certificate can be valid",
+            duration.num_seconds()
+        ))
+    })
+}
+
+/// Expands a leading `~` to the home directory
+pub fn resolve_path(input_path: &str) -> error::Result<PathBuf> {
+    if !input_path.starts_with('~') {
+        return Ok(PathBuf::from(input_path));
+    }
+
+    let home = env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .map_err(|_| CoralGateError::MissingEnvironment("HOME".into()))?;
+
+    let mut path = PathBuf::from(home);
+    let stripped = input_path
+        .strip_prefix("~/")
+        .unwrap_or(input_path.strip_prefix("~").unwrap_or(""));
+    path.push(stripped);
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30m"), Ok(chrono::Duration::minutes(30)));
+        assert_eq!(parse_duration(" 1h30m "), Ok(chrono::Duration::minutes(90)));
+        assert_eq!(parse_duration("2w1d"), Ok(chrono::Duration::days(15)));
+        assert_eq!(parse_duration("45s"), Ok(chrono::Duration::seconds(45)));
+    }
+
+    #[test]
+    fn refuses_invalid_durations() {
+        for value in ["", "30", "m", "0m", "1y", "1h30", "-1h"] {
+            assert!(parse_duration(value).is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn refuses_overflowing_durations() {
+        let error = parse_duration("99999999999999d").unwrap_err();
+        assert!(error.contains("too long"), "{}", error);
+
+        assert!(parse_duration("9223372036854775807s1s").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn expiration_seconds_fit_an_i32() {
+        assert_eq!(
+            expiration_seconds(chrono::Duration::days(1)).unwrap(),
+            86400
+        );
+        assert!(matches!(
+            expiration_seconds(chrono::Duration::days(100_000)),
+            Err(CoralGateError::InvalidDuration(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_expiries_past_the_last_date() {
+        assert!(from_now(chrono::Duration::hours(1)).is_ok());
+        assert!(matches!(
+            from_now(chrono::Duration::MAX),
+            Err(CoralGateError::InvalidDuration(_))
+        ));
+    }
+
+    #[test]
+    fn sanitizes_names() {
+        assert_eq!(sanitize_name("Alice"), "alice");
+        assert_eq!(
+            sanitize_name("bob.smith@example.com"),
+            "bob-smith-example-com"
+        );
+        assert_eq!(sanitize_name("--system:admin--"), "system-admin");
+        assert_eq!(sanitize_name(&"a".repeat(100)).len(), 63);
+        assert_eq!(sanitize_name("..."), "");
+    }
+
+    #[test]
+    fn unique_labels_tell_colliding_names_apart() {
+        let labels = ["bob.smith", "bob-smith", "Alice", "alice"].map(unique_label);
+
+        assert_ne!(labels[0], labels[1]);
+        assert_ne!(labels[2], labels[3]);
+        assert!(labels[0].starts_with("bob-smith-"), "{}", labels[0]);
+        assert_eq!(unique_label("bob.smith"), labels[0]);
+        assert_eq!(unique_label(&"a".repeat(100)).len(), 63);
+        assert_eq!(unique_label("...").len(), 8);
+    }
+}